@@ -1,12 +1,25 @@
-use std::sync::{Arc, Mutex};
+use std::{path::Path, sync::{Arc, Mutex}};
 
 use replay_buffer::ReplayBuffer;
 
+use crate::date::DateTime;
+
 
 #[derive(Clone)]
 pub enum DataSource {
     Empty,
     Memory(Arc<ReplayBuffer<u8>>),
+    File(Arc<Path>),
+}
+
+impl DataSource {
+    pub fn label(&self) -> &'static str {
+        match self {
+            DataSource::Empty => "-",
+            DataSource::Memory(_) => "Memory",
+            DataSource::File(_) => "Local",
+        }
+    }
 }
 
 pub struct Cache {
@@ -25,3 +38,30 @@ impl Cache {
     }
 }
 
+
+/// A package being fetched from a mirror into the cache.
+#[derive(Clone)]
+pub struct Download {
+    pub filename: Arc<str>,
+    pub url: Arc<str>,
+    pub size: usize,
+    pub started: DateTime,
+    pub buffer: Arc<ReplayBuffer<u8>>,
+}
+
+#[derive(Default)]
+pub struct Downloads {
+    active: Mutex<Vec<Download>>,
+}
+
+impl Downloads {
+    pub fn add(&self, download: Download) {
+        self.active.lock().unwrap().push(download);
+    }
+    pub fn remove(&self, buffer: &Arc<ReplayBuffer<u8>>) {
+        self.active.lock().unwrap().retain(|v| !Arc::ptr_eq(&v.buffer, buffer));
+    }
+    pub fn list(&self) -> Vec<Download> {
+        self.active.lock().unwrap().clone()
+    }
+}
@@ -1,17 +1,62 @@
-use std::{sync::Arc, time::Duration};
+use std::{fmt::Display, io::ErrorKind, path::PathBuf, sync::Arc, time::Duration};
 
+use anyhow::Context;
 use serde::{Serialize, Deserialize};
-use crate::database::mirror::Mirror;
+use crate::database::{local::LocalRepo, mirror::Mirror, prefetch::PrefetchConfig, repo::state::Thresholds, rules::Rule, snapshot::SnapshotConfig};
 
+pub use args::Args;
+pub use overrides::Overrides;
+pub use secret::Secret;
 
+pub mod args;
+pub mod duration;
+pub mod overrides;
+pub mod secret;
+pub mod validate;
+
+
+/// Settings are merged in this order, each layer replacing the fields it sets:
+///
+/// 1. built-in defaults ([`Config::default`])
+/// 2. the config file (`config.toml`, or `--config <path>`)
+/// 3. `PACMAN_MIRROR_*` environment variables
+/// 4. command line flags (`--<field> <value>`)
 #[derive(Debug,Serialize,Deserialize)]
 pub struct Config {
     pub name: Arc<str>,
     pub listen: Arc<str>,
     pub arch: Arc<str>,
+    #[serde(with = "duration")]
     pub timeout: Duration,
+    #[serde(default)]
     pub repos: Vec<Arc<str>>,
+    #[serde(default)]
     pub mirrors: Vec<Mirror>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub local: Vec<LocalRepo>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<Rule>,
+    #[serde(default)]
+    pub thresholds: Thresholds,
+    /// How long each refresh's changes are listed under `/{repo}/changes`.
+    #[serde(default = "default_changelog_retention", with = "duration")]
+    pub changelog_retention: Duration,
+    #[serde(default)]
+    pub prefetch: PrefetchConfig,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snapshots: Option<SnapshotConfig>,
+    /// Where each repo's packages are saved after a refresh, to be loaded again at startup.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state_dir: Option<PathBuf>,
+    /// Names of channels serving repos pinned to snapshots, under `/{channel}/{repo}/`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub channels: Vec<Arc<str>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub admin_tokens: Vec<Secret>,
+}
+
+fn default_changelog_retention() -> Duration {
+    Duration::from_secs(30 * 24 * 3600)
 }
 
 impl Default for Config {
@@ -23,22 +68,44 @@ impl Default for Config {
             timeout: Duration::from_secs(3600),
             repos: vec!["core".into(), "multilib".into(), "extra".into()],
             mirrors: vec![Mirror::new("https://geo.mirror.pkgbuild.com/$repo/os/$arch/".into())],
+            local: Vec::new(),
+            rules: Vec::new(),
+            thresholds: Thresholds::default(),
+            changelog_retention: default_changelog_retention(),
+            prefetch: PrefetchConfig::default(),
+            snapshots: None,
+            state_dir: None,
+            channels: Vec::new(),
+            admin_tokens: Vec::new(),
         }
     }
 }
 
 impl Config {
-    fn try_load(path: &str) -> anyhow::Result<Self> {
-        let data = std::fs::read_to_string(path)?;
-        Ok(toml::from_str(&data)?)
-    }
-    pub fn load(path: &str) -> anyhow::Result<Self> {
-        if let Ok(cfg) = Self::try_load(path) {
-            return Ok(cfg);
+    fn read_table(path: &str) -> anyhow::Result<toml::Table> {
+        match std::fs::read_to_string(path) {
+            Ok(data) => toml::from_str(&data).with_context(|| format!("Failed to parse {path}")),
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                let table = toml::Table::try_from(Self::default())?;
+                std::fs::write(path, table.to_string())?;
+                Ok(table)
+            }
+            Err(err) => Err(err).with_context(|| format!("Failed to read {path}")),
         }
-        let cfg = Self::default();
-        std::fs::write(path, toml::to_string(&cfg)?)?;
-        Ok(cfg)
+    }
+    pub fn repo_names(&self) -> impl Iterator<Item = &Arc<str>> {
+        self.repos.iter().chain(self.local.iter().map(|v| &v.name))
+    }
+    pub fn load(path: &str, overrides: &Overrides) -> anyhow::Result<Self> {
+        let mut table = Self::read_table(path)?;
+        overrides.apply(&mut table, &toml::Table::try_from(Self::default())?)?;
+        Ok(table.try_into()?)
     }
 }
 
+impl Display for Config {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let table = toml::Table::try_from(self).map_err(|_| std::fmt::Error)?;
+        write!(f, "{table}")
+    }
+}
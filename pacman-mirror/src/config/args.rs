@@ -0,0 +1,66 @@
+use anyhow::bail;
+
+use crate::config::{overrides::ENV_PREFIX, Overrides};
+
+
+pub const USAGE: &str = "\
+Usage: pacman-mirror [OPTIONS] [--<field> <value>]...
+
+Options:
+  --config <path>   Config file to load (default: config.toml, or $PACMAN_MIRROR_CONFIG)
+  --print-config    Print the effective configuration and exit
+  --help            Print this message and exit
+
+Any config field can be set with --<field> <value> or PACMAN_MIRROR_<FIELD>=<value>.
+Nested fields use dots on the command line (--foo.bar) and double underscores
+in the environment (PACMAN_MIRROR_FOO__BAR). Later sources win:
+defaults < config file < environment < command line.";
+
+pub struct Args {
+    pub config: String,
+    pub print_config: bool,
+    pub print_help: bool,
+    pub overrides: Overrides,
+}
+
+impl Args {
+    pub fn parse(env: impl IntoIterator<Item = (String, String)>, args: impl IntoIterator<Item = String>) -> anyhow::Result<Self> {
+        let env = Vec::from_iter(env);
+        let config = env.iter()
+            .find(|(k, _)| k.strip_prefix(ENV_PREFIX) == Some("CONFIG"))
+            .map(|(_, v)| v.clone());
+        let mut result = Self {
+            config: config.unwrap_or_else(|| "config.toml".into()),
+            print_config: false,
+            print_help: false,
+            overrides: Overrides::from_env(env),
+        };
+        let mut cli = Overrides::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let Some(flag) = arg.strip_prefix("--") else {
+                bail!("Unexpected argument: {arg}\n\n{USAGE}");
+            };
+            let (flag, value) = match flag.split_once('=') {
+                Some((k, v)) => (k, Some(v.to_owned())),
+                None => (flag, None),
+            };
+            match flag {
+                "print-config" => result.print_config = true,
+                "help" => result.print_help = true,
+                _ => {
+                    let Some(value) = value.or_else(|| args.next()) else {
+                        bail!("Missing value for --{flag}\n\n{USAGE}");
+                    };
+                    match flag {
+                        "config" => result.config = value,
+                        _ => cli.push_arg(flag, value),
+                    }
+                }
+            }
+        }
+        result.overrides.extend(cli);
+        Ok(result)
+    }
+}
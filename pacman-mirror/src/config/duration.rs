@@ -0,0 +1,156 @@
+//! Durations written as `"1h"`, `"15m"`, `"90s"` or combinations like `"1h30m"`.
+//!
+//! Use with `#[serde(with = "crate::config::duration")]`, or
+//! `crate::config::duration::option` for `Option<Duration>` fields.
+//! The `{ secs = 3600, nanos = 0 }` table and plain seconds are still accepted.
+
+use std::{fmt::Write, time::Duration};
+
+use serde::{de::{self, MapAccess, Visitor}, Deserializer, Serializer};
+
+
+const UNITS: [(&str, u64); 6] = [
+    ("w", 7 * 24 * 3600 * 1000),
+    ("d", 24 * 3600 * 1000),
+    ("h", 3600 * 1000),
+    ("m", 60 * 1000),
+    ("s", 1000),
+    ("ms", 1),
+];
+
+pub fn parse(src: &str) -> Result<Duration, String> {
+    let mut rest = src.trim();
+    let mut total = Duration::ZERO;
+    if rest.is_empty() {
+        return Err("empty duration".into());
+    }
+    while !rest.is_empty() {
+        let num_len = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        let unit_len = rest[num_len..].find(|c: char| !c.is_ascii_alphabetic()).map_or(rest.len(), |v| v + num_len);
+        let (num, unit) = (&rest[..num_len], &rest[num_len..unit_len]);
+        let Ok(num) = num.parse::<u64>() else {
+            return Err(format!("expected a number at {rest:?}"));
+        };
+        let Some((_, millis)) = UNITS.iter().find(|(name, _)| *name == unit) else {
+            return Err(format!("unknown unit {unit:?} in {src:?}, expected one of w, d, h, m, s or ms"));
+        };
+        let Some(next) = num.checked_mul(*millis).and_then(|v| total.checked_add(Duration::from_millis(v))) else {
+            return Err(format!("duration {src:?} is too large"));
+        };
+        total = next;
+        rest = rest[unit_len..].trim_start();
+    }
+    Ok(total)
+}
+
+pub fn format(value: Duration) -> String {
+    let mut millis = value.as_millis();
+    let mut dst = String::new();
+    for (name, size) in UNITS.iter().skip(1) {
+        let size = *size as u128;
+        if millis >= size {
+            _ = write!(dst, "{}{name}", millis / size);
+            millis %= size;
+        }
+    }
+    if dst.is_empty() {
+        dst.push_str("0s");
+    }
+    dst
+}
+
+struct DurationVisitor;
+
+impl<'de> Visitor<'de> for DurationVisitor {
+    type Value = Duration;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "a duration such as \"1h\", \"15m\" or \"90s\"")
+    }
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Duration, E> {
+        parse(v).map_err(E::custom)
+    }
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Duration, E> {
+        Ok(Duration::from_secs(v))
+    }
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Duration, E> {
+        u64::try_from(v).map(Duration::from_secs).map_err(|_| E::custom("duration must not be negative"))
+    }
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Duration, A::Error> {
+        let (mut secs, mut nanos) = (0u64, 0u32);
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "secs" => secs = map.next_value()?,
+                "nanos" => nanos = map.next_value()?,
+                _ => return Err(de::Error::unknown_field(&key, &["secs", "nanos"])),
+            }
+        }
+        Ok(Duration::new(secs, nanos))
+    }
+}
+
+pub fn serialize<S: Serializer>(value: &Duration, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str(&format(*value))
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Duration, D::Error> {
+    d.deserialize_any(DurationVisitor)
+}
+
+pub mod option {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    #[derive(Deserialize)]
+    struct Wrapper(#[serde(with = "super")] Duration);
+
+    pub fn serialize<S: Serializer>(value: &Option<Duration>, s: S) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(v) => super::serialize(v, s),
+            None => s.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Duration>, D::Error> {
+        Ok(Option::<Wrapper>::deserialize(d)?.map(|v| v.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_format() {
+        assert_eq!(parse("1h30m"), Ok(Duration::from_secs(5400)));
+        assert_eq!(parse(" 2d 12h "), Ok(Duration::from_secs(60 * 3600)));
+        assert_eq!(parse("1w"), Ok(Duration::from_secs(7 * 24 * 3600)));
+        assert_eq!(parse("250ms"), Ok(Duration::from_millis(250)));
+        assert!(parse("").is_err());
+        assert!(parse("15").is_err());
+        assert!(parse("h").is_err());
+        assert!(parse("3y").is_err());
+        assert!(parse("18446744073709551615w").is_err());
+        assert!(parse(&"18446744073709551615ms ".repeat(1001)).is_err());
+        assert_eq!(format(Duration::from_secs(5400)), "1h30m");
+        // weeks are read but never written
+        assert_eq!(format(Duration::from_secs(8 * 24 * 3600)), "8d");
+        assert_eq!(format(Duration::ZERO), "0s");
+        assert_eq!(parse(&format(Duration::from_millis(90_061_001))), Ok(Duration::from_millis(90_061_001)));
+    }
+
+    #[test]
+    fn deserialize() {
+        #[derive(serde::Deserialize)]
+        struct Config {
+            #[serde(with = "super")]
+            timeout: Duration,
+        }
+        let timeout = |src| toml::from_str::<Config>(src).map(|v| v.timeout).ok();
+        assert_eq!(timeout("timeout = \"90s\""), Some(Duration::from_secs(90)));
+        assert_eq!(timeout("timeout = 90"), Some(Duration::from_secs(90)));
+        assert_eq!(timeout("timeout = { secs = 90, nanos = 0 }"), Some(Duration::from_secs(90)));
+        assert_eq!(timeout("timeout = -1"), None);
+    }
+}
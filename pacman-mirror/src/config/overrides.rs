@@ -0,0 +1,127 @@
+use anyhow::bail;
+use toml::{Table, Value};
+
+
+pub const ENV_PREFIX: &str = "PACMAN_MIRROR_";
+
+struct Override {
+    origin: String,
+    key: Vec<String>,
+    value: String,
+}
+
+/// Config fields set from outside the config file.
+///
+/// Keys are dotted paths into the config, so `--timeout 1h` sets `timeout` and
+/// `PACMAN_MIRROR_FOO__BAR` sets `foo.bar`. Values are parsed as TOML values of
+/// the field's type, falling back to a plain string, so `--name 123` stays a
+/// name. A value that isn't valid TOML for an array field is split on commas,
+/// so `PACMAN_MIRROR_REPOS=core,extra` works.
+#[derive(Default)]
+pub struct Overrides {
+    items: Vec<Override>,
+}
+
+impl Overrides {
+    pub fn from_env(vars: impl IntoIterator<Item = (String, String)>) -> Self {
+        let mut items = Vec::new();
+        for (name, value) in vars {
+            let Some(key) = name.strip_prefix(ENV_PREFIX) else {
+                continue;
+            };
+            if key == "CONFIG" {
+                continue;
+            }
+            items.push(Override {
+                key: key.split("__").map(|v| v.to_lowercase()).collect(),
+                origin: name,
+                value,
+            });
+        }
+        items.sort_by(|a, b| a.origin.cmp(&b.origin));
+        Self { items }
+    }
+    pub fn push_arg(&mut self, flag: &str, value: String) {
+        self.items.push(Override {
+            origin: format!("--{flag}"),
+            key: flag.split('.').map(|v| v.replace('-', "_")).collect(),
+            value,
+        });
+    }
+    pub fn extend(&mut self, other: Overrides) {
+        self.items.extend(other.items);
+    }
+    /// Fields missing from `table` take their type from `defaults`.
+    pub fn apply(&self, table: &mut Table, defaults: &Table) -> anyhow::Result<()> {
+        for item in self.items.iter() {
+            let Some((last, parents)) = item.key.split_last() else {
+                bail!("{}: empty key", item.origin);
+            };
+            let mut dst = &mut *table;
+            let mut defaults = Some(defaults);
+            for part in parents {
+                dst = match dst.entry(part.as_str()).or_insert_with(|| Value::Table(Table::new())) {
+                    Value::Table(v) => v,
+                    _ => bail!("{}: {part} is not a table", item.origin),
+                };
+                defaults = defaults.and_then(|v| v.get(part.as_str())).and_then(Value::as_table);
+            }
+            let current = dst.get(last.as_str()).or_else(|| defaults.and_then(|v| v.get(last.as_str())));
+            let value = parse_value(&item.value, current);
+            dst.insert(last.clone(), value);
+        }
+        Ok(())
+    }
+}
+
+fn parse_value(raw: &str, current: Option<&Value>) -> Value {
+    let parsed = toml::from_str::<Table>(&format!("v = {raw}")).ok().and_then(|mut v| v.remove("v"));
+    match (current, parsed) {
+        (Some(Value::String(_)), _) => Value::String(raw.into()),
+        (Some(Value::Float(_)), Some(Value::Integer(v))) => Value::Float(v as f64),
+        (Some(current), Some(value)) if current.same_type(&value) => value,
+        (None, Some(value)) => value,
+        (Some(Value::Array(_)), _) => Value::Array(raw.split(',')
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(|v| Value::String(v.into()))
+            .collect()),
+        _ => Value::String(raw.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(table: &str, defaults: &str, args: &[(&str, &str)]) -> Table {
+        let mut table = toml::from_str(table).unwrap();
+        let mut overrides = Overrides::default();
+        for (flag, value) in args {
+            overrides.push_arg(flag, value.to_string());
+        }
+        overrides.apply(&mut table, &toml::from_str(defaults).unwrap()).unwrap();
+        table
+    }
+
+    #[test]
+    fn keeps_the_field_type() {
+        let table = apply("name = \"arch\"\nrepos = [\"core\"]", "", &[("name", "123"), ("repos", "core,extra")]);
+        assert_eq!(table["name"], Value::String("123".into()));
+        assert_eq!(table["repos"], Value::Array(vec![Value::String("core".into()), Value::String("extra".into())]));
+    }
+
+    #[test]
+    fn types_missing_fields_from_defaults() {
+        let table = apply("", "name = \"arch\"\n[thresholds]\nmax_removed = 50.0", &[("name", "true"), ("thresholds.max-removed", "20")]);
+        assert_eq!(table["name"], Value::String("true".into()));
+        assert_eq!(table["thresholds"]["max_removed"], Value::Float(20.0));
+    }
+
+    #[test]
+    fn parses_unknown_fields_as_toml() {
+        let table = apply("", "", &[("prefetch.concurrency", "4"), ("state-dir", "/var/lib/pacman-mirror")]);
+        assert_eq!(table["prefetch"]["concurrency"], Value::Integer(4));
+        assert_eq!(table["state_dir"], Value::String("/var/lib/pacman-mirror".into()));
+    }
+}
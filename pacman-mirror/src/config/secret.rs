@@ -0,0 +1,56 @@
+//! Passwords and tokens, which are never printed.
+
+use std::fmt::Debug;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+
+const REDACTED: &str = "<redacted>";
+
+/// A string read from the config that shows up as `<redacted>` in logs and
+/// `--print-config`. Use [`Secret::expose`] where the value is needed.
+#[derive(Clone,Eq,Hash,PartialEq)]
+pub struct Secret(Box<str>);
+
+impl Secret {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&str> for Secret {
+    fn from(value: &str) -> Self {
+        Self(value.into())
+    }
+}
+
+impl Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl Serialize for Secret {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(REDACTED)
+    }
+}
+
+impl<'de> Deserialize<'de> for Secret {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Box::<str>::deserialize(deserializer).map(Self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redacted() {
+        let secret = Secret::from("hunter2");
+        assert_eq!(format!("{secret:?}"), "<redacted>");
+        assert_eq!(toml::Value::try_from(&secret).unwrap().as_str(), Some("<redacted>"));
+        assert_eq!(secret.expose(), "hunter2");
+    }
+}
@@ -0,0 +1,207 @@
+use std::{collections::HashSet, fmt::Display, net::ToSocketAddrs, path::PathBuf, sync::Arc};
+
+use thiserror::Error;
+
+use crate::{database::{mirror::MirrorProblem, rules::{Action, Rule}}, Config};
+
+
+/// Top level paths that can't be used as repo names.
+pub const RESERVED: &[&str] = &["snapshot", "admin", "api", "search", "files", "prefetch", "metrics", "healthz", "readyz"];
+
+#[derive(Debug,Error)]
+pub enum Problem {
+    #[error("listen: {value:?} is not a valid address ({err}), expected host:port such as \"0.0.0.0:8080\"")]
+    Listen { value: Arc<str>, err: std::io::Error },
+    #[error("name: must not be empty")]
+    EmptyName,
+    #[error("arch: must not be empty, e.g. \"x86_64\"")]
+    EmptyArch,
+    #[error("timeout: must be greater than zero")]
+    ZeroTimeout,
+    #[error("repos: no repositories configured, e.g. repos = [\"core\", \"extra\"] or a [[local]] repo")]
+    NoRepos,
+    #[error("repos: {0:?} is listed more than once")]
+    DuplicateRepo(Arc<str>),
+    #[error("repos: {0:?} is not a valid repository name, it must be non-empty and not contain '/' or '.'")]
+    InvalidRepo(Arc<str>),
+    #[error("repos: {0:?} is reserved for the server's own pages")]
+    ReservedRepo(Arc<str>),
+    #[error("local: {name:?}: {path:?} is not a directory")]
+    LocalPath { name: Arc<str>, path: PathBuf },
+    #[error("local: {name:?}: overlay {overlay:?} is not one of the upstream repos")]
+    UnknownOverlay { name: Arc<str>, overlay: Arc<str> },
+    #[error("rules: {0}: repo is not configured")]
+    RuleRepo(Rule),
+    #[error("rules: {0}: package pattern is empty")]
+    RulePattern(Rule),
+    #[error("thresholds: max_removed is {0}, expected a percentage from 0 to 100")]
+    MaxRemoved(f64),
+    #[error("prefetch: concurrency must be greater than zero")]
+    ZeroConcurrency,
+    #[error("prefetch: watch pattern {0:?} is empty")]
+    WatchPattern(Box<str>),
+    #[error("snapshots: retention must be greater than zero")]
+    ZeroRetention,
+    #[error("channels: {0:?} is not a valid channel name, it must be non-empty and not contain '/' or '.'")]
+    InvalidChannel(Arc<str>),
+    #[error("channels: {0:?} is already used by a repo, another channel or the server's own pages")]
+    ChannelConflict(Arc<str>),
+    #[error("channels: serving channels needs snapshots to be enabled")]
+    ChannelsNeedSnapshots,
+    #[error("mirrors: no mirrors configured")]
+    NoMirrors,
+    #[error("mirrors: {0:?} is listed more than once")]
+    DuplicateMirror(Box<str>),
+    #[error("mirrors: {url:?}: {problem}")]
+    Mirror { url: Box<str>, problem: MirrorProblem },
+}
+
+#[derive(Debug,Error)]
+pub struct ValidationError(pub Vec<Problem>);
+
+impl Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid configuration ({} problems):", self.0.len())?;
+        for problem in self.0.iter() {
+            write!(f, "\n  - {problem}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Config {
+    pub fn problems(&self) -> Vec<Problem> {
+        let mut problems = Vec::new();
+
+        if let Err(err) = self.listen.to_socket_addrs() {
+            problems.push(Problem::Listen { value: self.listen.clone(), err });
+        }
+        if self.name.is_empty() {
+            problems.push(Problem::EmptyName);
+        }
+        if self.arch.is_empty() {
+            problems.push(Problem::EmptyArch);
+        }
+        if self.timeout.is_zero() {
+            problems.push(Problem::ZeroTimeout);
+        }
+
+        if self.repo_names().next().is_none() {
+            problems.push(Problem::NoRepos);
+        }
+        let mut repos = HashSet::new();
+        for repo in self.repo_names() {
+            if repo.is_empty() || repo.contains(['/', '.']) {
+                problems.push(Problem::InvalidRepo(repo.clone()));
+            }
+            if RESERVED.contains(&repo.as_ref()) {
+                problems.push(Problem::ReservedRepo(repo.clone()));
+            }
+            if !repos.insert(repo) {
+                problems.push(Problem::DuplicateRepo(repo.clone()));
+            }
+        }
+
+        for local in self.local.iter() {
+            if !local.path.is_dir() {
+                problems.push(Problem::LocalPath { name: local.name.clone(), path: local.path.clone() });
+            }
+            for overlay in local.overlays.iter().filter(|v| !self.repos.contains(v)) {
+                problems.push(Problem::UnknownOverlay { name: local.name.clone(), overlay: overlay.clone() });
+            }
+        }
+
+        for rule in self.rules.iter() {
+            if rule.repo.as_ref().is_some_and(|v| !self.repo_names().any(|name| name == v)) {
+                problems.push(Problem::RuleRepo(rule.clone()));
+            }
+            let empty = match &rule.action {
+                Action::Hold { package, .. } | Action::Exclude { package } => package.is_empty(),
+                Action::Allow { packages } => packages.is_empty() || packages.iter().any(|v| v.is_empty()),
+            };
+            if empty {
+                problems.push(Problem::RulePattern(rule.clone()));
+            }
+        }
+
+        if !(0.0..=100.0).contains(&self.thresholds.max_removed) {
+            problems.push(Problem::MaxRemoved(self.thresholds.max_removed));
+        }
+
+        if self.prefetch.concurrency == 0 {
+            problems.push(Problem::ZeroConcurrency);
+        }
+        for pattern in self.prefetch.watch.iter().filter(|v| v.is_empty() || v.ends_with('/')) {
+            problems.push(Problem::WatchPattern(pattern.clone()));
+        }
+
+        if self.snapshots.as_ref().is_some_and(|v| v.retention.is_zero()) {
+            problems.push(Problem::ZeroRetention);
+        }
+
+        for channel in self.channels.iter() {
+            if channel.is_empty() || channel.contains(['/', '.']) {
+                problems.push(Problem::InvalidChannel(channel.clone()));
+            }
+            if RESERVED.contains(&channel.as_ref()) || !repos.insert(channel) {
+                problems.push(Problem::ChannelConflict(channel.clone()));
+            }
+        }
+        if !self.channels.is_empty() && self.snapshots.is_none() {
+            problems.push(Problem::ChannelsNeedSnapshots);
+        }
+
+        // local repos don't need any
+        if self.mirrors.is_empty() && !self.repos.is_empty() {
+            problems.push(Problem::NoMirrors);
+        }
+        let mut mirrors = HashSet::new();
+        for mirror in self.mirrors.iter() {
+            if !mirrors.insert(mirror.url()) {
+                problems.push(Problem::DuplicateMirror(mirror.url().into()));
+            }
+            for problem in mirror.problems() {
+                problems.push(Problem::Mirror { url: mirror.url().into(), problem });
+            }
+        }
+        problems
+    }
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        let problems = self.problems();
+        if problems.is_empty() {
+            return Ok(());
+        }
+        Err(ValidationError(problems))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn problems(src: &str) -> Vec<Problem> {
+        let config: Config = toml::from_str(&format!("name = \"test\"\nlisten = \"127.0.0.1:8080\"\narch = \"x86_64\"\ntimeout = \"1h\"\n{src}")).unwrap();
+        config.problems()
+    }
+
+    #[test]
+    fn local_only() {
+        let dir = std::env::temp_dir();
+        let found = problems(&format!("[[local]]\nname = \"custom\"\npath = {:?}", dir.display().to_string()));
+        assert!(found.is_empty(), "{found:?}");
+    }
+
+    #[test]
+    fn nothing_configured() {
+        let found = problems("");
+        assert!(matches!(found.as_slice(), [Problem::NoRepos]), "{found:?}");
+        let found = problems("repos = [\"core\"]");
+        assert!(matches!(found.as_slice(), [Problem::NoMirrors]), "{found:?}");
+    }
+
+    #[test]
+    fn reserved_and_duplicate_repos() {
+        let found = problems("repos = [\"api\", \"core\", \"core\"]\nmirrors = [\"https://example.com/$repo/os/$arch\"]");
+        assert!(matches!(found.as_slice(), [Problem::ReservedRepo(_), Problem::DuplicateRepo(_)]), "{found:?}");
+    }
+}
@@ -1,26 +1,62 @@
-use std::{collections::HashMap, sync::Arc};
-use crate::Config;
+use std::{collections::HashMap, sync::{Arc, Mutex}};
+use itertools::Itertools;
+use log::error;
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use crate::{database::{channel::Channels, local::LocalIndex, mirror_data::MirrorData, prefetch::Prefetcher, snapshot::Snapshots}, Config};
 
 pub use repo::Repo;
 
+pub mod archive;
+pub mod channel;
+pub mod constraint;
 pub mod desc;
+pub mod graph;
+pub mod local;
 pub mod mirror;
 pub mod package;
+pub mod prefetch;
 pub mod repo;
+pub mod rules;
+pub mod search;
+pub mod snapshot;
 pub mod mirror_data;
 
 pub struct Database {
     pub repos: HashMap<Arc<str>, Arc<Repo>>,
+    pub snapshots: Arc<Snapshots>,
+    pub prefetch: Arc<Prefetcher>,
+    pub channels: Channels,
+    graph: Mutex<graph::Built>,
     pub config: Arc<Config>,
 }
 
 impl Database {
     pub fn new(config: Arc<Config>) -> Self {
         let mut repos = HashMap::new();
+        let snapshots = Arc::new(Snapshots::new(&config));
+        let prefetch = Arc::new(Prefetcher::new(&config));
+        let locals = config.local.iter()
+            .map(|local| (local, Arc::new(LocalIndex::new(local.path.clone()))))
+            .collect_vec();
         for name in config.repos.iter().cloned() {
-            repos.insert(name.clone(), Arc::new(Repo::empty(config.clone(), name)));
+            // a view of its own, so refreshing one repo never resets what another serves
+            let overlays = locals.iter()
+                .filter(|(local, _)| local.overlays.contains(&name))
+                .map(|(local, index)| Arc::new(MirrorData::new_local(local, index.clone())))
+                .collect();
+            repos.insert(name.clone(), Arc::new(Repo::empty(config.clone(), snapshots.clone(), prefetch.clone(), name, overlays)));
         }
-        Self { repos, config }
+        for (local, index) in locals {
+            let mirror = Arc::new(MirrorData::new_local(local, index));
+            repos.insert(local.name.clone(), Arc::new(Repo::local(config.clone(), snapshots.clone(), prefetch.clone(), mirror)));
+        }
+        repos.par_iter().for_each(|(name, repo)| {
+            if let Err(err) = repo.load_state() {
+                error!("Failed to load saved state of {name}: {err:?}");
+            }
+        });
+        let channels = Channels::new(&config, snapshots.clone());
+        Self { repos, snapshots, prefetch, channels, graph: Mutex::default(), config }
     }
 }
 
@@ -0,0 +1,131 @@
+use std::{ffi::OsString, io::{Cursor, Read, Write}, path::PathBuf, sync::Arc, time::SystemTime};
+
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use log::warn;
+use tar::{EntryType, Header};
+
+use crate::database::{desc::Desc, repo::state::FetchType};
+
+
+struct PartialPackage {
+    name: OsString,
+    desc: Option<Desc>,
+    files: Option<Arc<str>>,
+}
+
+impl PartialPackage {
+    pub fn new(name: OsString) -> Self {
+        Self { name, desc: None, files: None }
+    }
+    pub fn into_desc(self, ty: FetchType) -> Option<Arc<Desc>> {
+        let mut desc = self.desc?;
+        if ty >= FetchType::Files {
+            desc.files = Some(self.files?);
+        }
+        Some(desc.into())
+    }
+}
+
+/// Reads a gzipped repo database (`{repo}.db` / `{repo}.files`).
+pub fn read(src: impl Read, fetch_ty: FetchType, mut dst: impl FnMut(Arc<Desc>)) -> anyhow::Result<()> {
+    let mut partial_pkg = Option::<PartialPackage>::None;
+    let mut archive = tar::Archive::new(GzDecoder::new(src));
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        let mut path_iter = path.iter();
+
+        let name = match path_iter.next() {
+            None => continue,
+            Some(v) => v,
+        };
+        let ty = match path_iter.next() {
+            None => continue,
+            Some(v) => v,
+        };
+        let partial_pkg = partial_pkg.get_or_insert_with(|| PartialPackage::new(name.into()));
+        if partial_pkg.name != name {
+            let pkg = std::mem::replace(partial_pkg, PartialPackage::new(name.into()));
+            if let Some(desc) = pkg.into_desc(fetch_ty) {
+                dst(desc);
+            }
+        }
+        if path_iter.next().is_some() {
+            continue;
+        }
+        let mut str = String::new();
+        if ty == "desc" {
+            entry.read_to_string(&mut str)?;
+            partial_pkg.desc = Some(Desc::parse(str.into())?);
+        }
+        else if ty == "files" {
+            entry.read_to_string(&mut str)?;
+            partial_pkg.files = Some(str.into());
+        }
+    }
+    if let Some(desc) = partial_pkg.take().and_then(|v| v.into_desc(fetch_ty)) {
+        dst(desc);
+    }
+    Ok(())
+}
+
+/// Writes a gzipped repo database in the same layout `repo-add` produces.
+pub struct Writer<W: Write> {
+    builder: tar::Builder<GzEncoder<W>>,
+    ty: FetchType,
+    now: u64,
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(dst: W, ty: FetchType, level: u32) -> Self {
+        Self {
+            builder: tar::Builder::new(GzEncoder::new(dst, Compression::new(level))),
+            ty,
+            now: SystemTime::UNIX_EPOCH.elapsed().map(|v| v.as_secs()).unwrap_or(0),
+        }
+    }
+
+    fn send_file(&mut self, path: PathBuf, bytes: &[u8]) -> anyhow::Result<()> {
+        self.builder.append(&{
+            let mut v = Header::new_gnu();
+            v.set_path(path)?;
+            v.set_entry_type(EntryType::file());
+            v.set_size(bytes.len().try_into()?);
+            v.set_mode(0o644);
+            v.set_mtime(self.now);
+            v.set_cksum();
+            v
+        }, Cursor::new(bytes))?;
+        Ok(())
+    }
+
+    pub fn append(&mut self, desc: &Desc) -> anyhow::Result<()> {
+        let path = PathBuf::from(format!("{}-{}", desc.name.as_ref(), desc.version.as_ref()));
+        self.builder.append(&{
+            let mut v = Header::new_gnu();
+            v.set_path(&path)?;
+            v.set_entry_type(EntryType::dir());
+            v.set_size(0);
+            v.set_mode(0o755);
+            v.set_mtime(self.now);
+            v.set_cksum();
+            v
+        }, std::io::empty())?;
+
+        self.send_file(path.join("desc"), &desc.write_to_vec()?)?;
+
+        if self.ty > FetchType::Db {
+            if let Some(files) = &desc.files {
+                self.send_file(path.join("files"), files.as_bytes())?;
+            } else {
+                warn!("Package {} is missing file info", desc.name);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> anyhow::Result<W> {
+        Ok(self.builder.into_inner()?.finish()?)
+    }
+}
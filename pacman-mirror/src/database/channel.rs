@@ -0,0 +1,237 @@
+use std::{collections::{BTreeMap, HashMap, HashSet}, io::ErrorKind, path::PathBuf, sync::{Arc, Mutex}};
+
+use anyhow::Context;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{database::{snapshot::Snapshots, Repo}, date::DateTime, Config};
+
+/// How many earlier pins each channel keeps for rollbacks.
+const HISTORY: usize = 10;
+
+/// Snapshot id by repo name.
+pub type Pins = BTreeMap<Arc<str>, Arc<str>>;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Channel {
+    #[serde(default)]
+    pub pins: Pins,
+    /// Earlier pins, newest last.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<Pins>,
+}
+
+#[derive(Debug, Error)]
+pub enum ChannelError {
+    #[error("Unknown channel {0:?}")]
+    UnknownChannel(Box<str>),
+    #[error("Unknown repo {0:?}")]
+    UnknownRepo(Box<str>),
+    #[error("{repo}: no snapshot matches {target:?}")]
+    NoSnapshot { repo: Arc<str>, target: Box<str> },
+    #[error("Snapshots are not enabled")]
+    NoSnapshots,
+    #[error("Nothing to roll back to")]
+    NoHistory,
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+pub enum Target {
+    /// What the repo serves now.
+    Current,
+    /// A snapshot id, or a `YYYY-MM-DD` date.
+    Snapshot(Box<str>),
+}
+
+/// Named, frozen views of the repos, each repo pinned to one of its snapshots.
+pub struct Channels {
+    path: Option<PathBuf>,
+    snapshots: Arc<Snapshots>,
+    channels: Mutex<BTreeMap<Arc<str>, Channel>>,
+}
+
+impl Channels {
+    pub fn new(config: &Config, snapshots: Arc<Snapshots>) -> Self {
+        let path = snapshots.dir().map(|v| v.join("channels.toml"));
+        let mut saved = match path.as_ref().map(|v| (v, std::fs::read_to_string(v))) {
+            Some((_, Ok(data))) => toml::from_str::<BTreeMap<Arc<str>, Channel>>(&data)
+                .inspect_err(|err| warn!("Failed to parse channels: {err}"))
+                .unwrap_or_default(),
+            Some((path, Err(err))) if err.kind() != ErrorKind::NotFound => {
+                warn!("Failed to read {}: {err}", path.display());
+                BTreeMap::new()
+            }
+            _ => BTreeMap::new(),
+        };
+        let channels = config.channels.iter()
+            .map(|name| (name.clone(), saved.remove(name).unwrap_or_default()))
+            .collect();
+        let channels = Self { path, snapshots, channels: Mutex::new(channels) };
+        channels.update_pinned(&channels.channels.lock().unwrap());
+        channels
+    }
+    pub fn contains(&self, name: &str) -> bool {
+        self.channels.lock().unwrap().contains_key(name)
+    }
+    pub fn get(&self, name: &str) -> Option<Channel> {
+        self.channels.lock().unwrap().get(name).cloned()
+    }
+    pub fn all(&self) -> BTreeMap<Arc<str>, Channel> {
+        self.channels.lock().unwrap().clone()
+    }
+    fn update_pinned(&self, channels: &BTreeMap<Arc<str>, Channel>) {
+        self.snapshots.set_pinned(channels.values()
+            .flat_map(|v| std::iter::once(&v.pins).chain(v.history.iter()))
+            .flat_map(|pins| pins.iter().map(|(repo, id)| (repo.clone(), id.clone())))
+            .collect::<HashSet<_>>());
+    }
+    fn save(&self, channels: &BTreeMap<Arc<str>, Channel>) -> anyhow::Result<()> {
+        self.update_pinned(channels);
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let part = path.with_extension("part");
+        std::fs::write(&part, toml::to_string(channels)?)
+            .with_context(|| format!("Failed to write {}", part.display()))?;
+        std::fs::rename(&part, path)?;
+        Ok(())
+    }
+    fn resolve(&self, repo: &Repo, target: &Target) -> Result<Arc<str>, ChannelError> {
+        let snapshot = match target {
+            Target::Current => {
+                let ty = repo.state.read().unwrap().ty;
+                if repo.should_refresh(ty) {
+                    repo.refresh_now(ty);
+                }
+                return self.snapshots.record(repo)?.ok_or(ChannelError::NoSnapshots);
+            }
+            Target::Snapshot(src) => match DateTime::parse_date(src) {
+                Some(date) => self.snapshots.find(repo, date)?,
+                None => self.snapshots.get(repo, src)?,
+            },
+        };
+        match snapshot {
+            Some(snapshot) => Ok(snapshot.id.clone()),
+            None => Err(ChannelError::NoSnapshot { repo: repo.name.clone(), target: match target {
+                Target::Snapshot(src) => src.clone(),
+                Target::Current => "current".into(),
+            }}),
+        }
+    }
+    /// Moves `repo`, or every repo, of a channel to `target`.
+    /// Snapshots are resolved before locking the channels, as that may refresh the repos.
+    pub fn promote(&self, repos: &HashMap<Arc<str>, Arc<Repo>>, name: &str, repo: Option<&str>, target: &Target) -> Result<Channel, ChannelError> {
+        if !self.contains(name) {
+            return Err(ChannelError::UnknownChannel(name.into()));
+        }
+        let targets = match repo {
+            Some(repo) => vec![repos.get(repo).ok_or_else(|| ChannelError::UnknownRepo(repo.into()))?],
+            None => repos.values().collect(),
+        };
+        let resolved = targets.into_iter()
+            .map(|repo| Ok((repo.name.clone(), self.resolve(repo, target)?)))
+            .collect::<Result<Vec<_>, ChannelError>>()?;
+
+        let mut channels = self.channels.lock().unwrap();
+        let Some(channel) = channels.get_mut(name) else {
+            return Err(ChannelError::UnknownChannel(name.into()));
+        };
+        let mut pins = channel.pins.clone();
+        pins.extend(resolved);
+        if pins == channel.pins {
+            return Ok(channel.clone());
+        }
+        let previous = std::mem::replace(&mut channel.pins, pins);
+        channel.history.push(previous);
+        if channel.history.len() > HISTORY {
+            channel.history.remove(0);
+        }
+        let channel = channel.clone();
+        self.save(&channels)?;
+        info!("Promoted channel {name}: {:?}", channel.pins);
+        Ok(channel)
+    }
+    /// Puts a channel back to the pins it had before its last promotion.
+    pub fn rollback(&self, name: &str) -> Result<Channel, ChannelError> {
+        let mut channels = self.channels.lock().unwrap();
+        let Some(channel) = channels.get_mut(name) else {
+            return Err(ChannelError::UnknownChannel(name.into()));
+        };
+        channel.pins = channel.history.pop().ok_or(ChannelError::NoHistory)?;
+        let channel = channel.clone();
+        self.save(&channels)?;
+        info!("Rolled back channel {name}: {:?}", channel.pins);
+        Ok(channel)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::Path, time::{Duration, SystemTime}};
+
+    use crate::database::{desc::Desc, mirror::Mirror, package::Package, prefetch::Prefetcher, snapshot::SnapshotConfig};
+
+    use super::*;
+
+    fn repo(dir: &Path) -> Arc<Repo> {
+        let config = Arc::new(Config {
+            mirrors: vec![Mirror::new("https://a/$repo".into())],
+            snapshots: Some(SnapshotConfig { dir: dir.into(), retention: Duration::from_secs(30 * 24 * 3600) }),
+            channels: vec!["stable".into()],
+            ..Config::default()
+        });
+        Arc::new(Repo::empty(config.clone(), Arc::new(Snapshots::new(&config)), Arc::new(Prefetcher::new(&config)), "core".into(), Vec::new()))
+    }
+
+    /// Serves `foo` at `version`, as freshly refreshed.
+    fn serve(repo: &Repo, version: &str) {
+        let mut state = repo.state.write().unwrap();
+        state.packages = HashMap::from([("foo".into(), Package::new(Desc::test("foo", version, &[])))]);
+        state.last_updated = SystemTime::now();
+    }
+
+    #[test]
+    fn promotes_and_rolls_back() {
+        let dir = std::env::temp_dir().join(format!("pacman-mirror-channels-{}", std::process::id()));
+        let repo = repo(&dir);
+        let repos = HashMap::from([(repo.name.clone(), repo.clone())]);
+        let channels = Channels::new(&repo.config, repo.snapshots.clone());
+        let old = DateTime::now().add_days(-2);
+        serve(&repo, "1-1");
+        let first = repo.snapshots.record_at(&repo, old).unwrap().unwrap();
+
+        let channel = channels.promote(&repos, "stable", None, &Target::Snapshot(first.to_string().into())).unwrap();
+        assert_eq!(channel.pins, Pins::from([("core".into(), first.clone())]));
+        assert_eq!(channel.history, [Pins::new()]);
+
+        // the current state becomes a new snapshot
+        serve(&repo, "2-1");
+        let channel = channels.promote(&repos, "stable", Some("core"), &Target::Current).unwrap();
+        let current = channel.pins["core"].clone();
+        assert_ne!(current, first);
+        assert_eq!(repo.snapshots.list("core"), [first.clone(), current.clone()]);
+        assert_eq!(channel.history.len(), 2);
+
+        // a date picks the last snapshot of that day, and promoting to the same pins changes nothing
+        let channel = channels.promote(&repos, "stable", None, &Target::Snapshot(old.date().into())).unwrap();
+        assert_eq!(channel.pins["core"], first);
+        assert_eq!(channel.history.len(), 3);
+        let channel = channels.promote(&repos, "stable", None, &Target::Snapshot(first.to_string().into())).unwrap();
+        assert_eq!(channel.history.len(), 3);
+
+        assert_eq!(channels.rollback("stable").unwrap().pins["core"], current);
+        assert_eq!(channels.rollback("stable").unwrap().pins["core"], first);
+        assert!(channels.rollback("stable").unwrap().pins.is_empty());
+        assert!(matches!(channels.rollback("stable"), Err(ChannelError::NoHistory)));
+        // and it's all saved
+        assert!(Channels::new(&repo.config, repo.snapshots.clone()).get("stable").unwrap().history.is_empty());
+
+        assert!(matches!(channels.promote(&repos, "beta", None, &Target::Current), Err(ChannelError::UnknownChannel(_))));
+        assert!(matches!(channels.promote(&repos, "stable", Some("extra"), &Target::Current), Err(ChannelError::UnknownRepo(_))));
+        let target = Target::Snapshot("2000-01-01".into());
+        assert!(matches!(channels.promote(&repos, "stable", None, &target), Err(ChannelError::NoSnapshot { .. })));
+        _ = std::fs::remove_dir_all(dir);
+    }
+}
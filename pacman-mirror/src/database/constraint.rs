@@ -0,0 +1,140 @@
+use std::{cmp::Ordering, fmt::Display};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Lt, Le, Eq, Ge, Gt,
+}
+
+/// A version requirement such as `<6.10` or `>=1:2.0-1`.
+///
+/// A version ending in `.x` or `.*` covers every version under that prefix,
+/// so `<=6.9.x` allows `6.9.12.arch1-1` but not `6.10`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Constraint {
+    pub op: Op,
+    pub version: Box<str>,
+}
+
+#[derive(Debug, Error)]
+#[error("invalid version constraint {0:?}, expected something like \"<6.10\" or \"<=6.9.x\"")]
+pub struct ConstraintError(Box<str>);
+
+impl Op {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Op::Lt => "<",
+            Op::Le => "<=",
+            Op::Eq => "=",
+            Op::Ge => ">=",
+            Op::Gt => ">",
+        }
+    }
+    /// Splits a leading operator off `src`.
+    pub fn split(src: &str) -> Option<(Op, &str)> {
+        [Op::Le, Op::Ge, Op::Lt, Op::Gt, Op::Eq].into_iter()
+            .find_map(|op| src.strip_prefix(op.as_str()).map(|rest| (op, rest)))
+    }
+}
+
+impl Constraint {
+    pub fn parse(src: &str) -> Result<Self, ConstraintError> {
+        let (op, version) = Op::split(src.trim()).unwrap_or((Op::Eq, src.trim()));
+        let version = version.trim();
+        if version.is_empty() || version.contains(char::is_whitespace) {
+            return Err(ConstraintError(src.into()));
+        }
+        Ok(Self { op, version: version.into() })
+    }
+    fn compare(&self, version: &str) -> Ordering {
+        let prefix = self.version.strip_suffix(".x").or_else(|| self.version.strip_suffix(".*"));
+        match prefix {
+            Some(prefix) if version == prefix || version.strip_prefix(prefix).is_some_and(|v| v.starts_with('.')) => Ordering::Equal,
+            Some(prefix) => vercmp::alpm_pkg_ver_cmp(version, prefix),
+            None => vercmp::alpm_pkg_ver_cmp(version, &self.version),
+        }
+    }
+    pub fn matches(&self, version: &str) -> bool {
+        let ord = self.compare(version);
+        match self.op {
+            Op::Lt => ord == Ordering::Less,
+            Op::Le => ord != Ordering::Greater,
+            Op::Eq => ord == Ordering::Equal,
+            Op::Ge => ord != Ordering::Less,
+            Op::Gt => ord == Ordering::Greater,
+        }
+    }
+}
+
+impl Display for Constraint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", self.op.as_str(), self.version)
+    }
+}
+
+impl TryFrom<String> for Constraint {
+    type Error = ConstraintError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::parse(&value)
+    }
+}
+
+impl From<Constraint> for String {
+    fn from(value: Constraint) -> Self {
+        value.to_string()
+    }
+}
+
+/// Splits a dependency such as `glibc>=2.38`, or an optional one such as
+/// `python: for scripts`, into the package name and its constraint.
+pub fn split_dep(src: &str) -> (&str, Option<Constraint>) {
+    let src = src.split_once(": ").map_or(src, |v| v.0).trim();
+    match src.find(['<', '>', '=']) {
+        Some(at) => (&src[..at], Constraint::parse(&src[at..]).ok()),
+        None => (src, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!(Constraint::parse("<6.10").unwrap(), Constraint { op: Op::Lt, version: "6.10".into() });
+        assert_eq!(Constraint::parse(" >= 1:2.0-1 ").unwrap(), Constraint { op: Op::Ge, version: "1:2.0-1".into() });
+        assert_eq!(Constraint::parse("1.0").unwrap().op, Op::Eq);
+        assert!(Constraint::parse("<").is_err());
+        assert!(Constraint::parse("<1 2").is_err());
+    }
+
+    #[test]
+    fn matches() {
+        let le = Constraint::parse("<=6.9.x").unwrap();
+        assert!(le.matches("6.9"));
+        assert!(le.matches("6.9.12.arch1-1"));
+        assert!(le.matches("6.8.1-1"));
+        assert!(!le.matches("6.10"));
+        assert!(!le.matches("6.90"));
+        let lt = Constraint::parse("<6.10").unwrap();
+        assert!(lt.matches("6.9.12-1"));
+        assert!(!lt.matches("6.10"));
+        assert!(Constraint::parse(">=1:1.0").unwrap().matches("1:1.0"));
+        assert!(!Constraint::parse(">=1:1.0").unwrap().matches("2.0"));
+        assert!(Constraint::parse("=2.0-1").unwrap().matches("2.0-1"));
+        assert!(Constraint::parse(">2.0").unwrap().matches("2.0.1"));
+    }
+
+    #[test]
+    fn split() {
+        assert_eq!(split_dep("glibc"), ("glibc", None));
+        assert_eq!(split_dep("glibc>=2.38"), ("glibc", Some(Constraint { op: Op::Ge, version: "2.38".into() })));
+        assert_eq!(split_dep("python: for scripts"), ("python", None));
+        assert_eq!(split_dep("sh=5: for the hooks"), ("sh", Some(Constraint { op: Op::Eq, version: "5".into() })));
+    }
+}
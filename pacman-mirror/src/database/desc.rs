@@ -15,14 +15,14 @@ pub struct Desc {
     pub name: Arc<str>,
     pub filename: Arc<str>,
     pub version: Arc<str>,
-    pub pgpsig: Arc<str>,
+    pub pgpsig: Option<Arc<str>>,
     pub builddate: SystemTime,
     pub sha256sum: [u8; 32],
     pub csize: usize,
 }
 
 enum Known {
-    Name, Filename, Version, PgpSig, Sha256Sum, BuildDate, Csize,
+    Name, Filename, Version, Sha256Sum, BuildDate, Csize, PgpSig,
 }
 
 impl Desc {
@@ -31,6 +31,9 @@ impl Desc {
         parser::parse(src, |k, v| {
             data.push((k.to_uppercase().into(), v.into()));
         })?;
+        Self::from_fields(data)
+    }
+    pub fn from_fields(data: Vec<(Arc<str>, Arc<str>)>) -> Result<Desc, ParseError> {
         let mut known: [Option<Arc<str>>; 7] = std::array::from_fn(|_| None);
         for (k, v) in data.iter() {
             known[match k.as_ref() {
@@ -44,7 +47,8 @@ impl Desc {
                 _ => continue,
             } as usize] = Some(v.clone());
         }
-        let known: Vec<Arc<str>> = known.into_iter().map(|v| v.ok_or(ParseError::MissingFields)).try_collect()?;
+        let pgpsig = known[Known::PgpSig as usize].take();
+        let known: Vec<Arc<str>> = known.into_iter().take(Known::PgpSig as usize).map(|v| v.ok_or(ParseError::MissingFields)).try_collect()?;
         let mut sha256sum = [0u8; 32];
         if let Err(err) = hex::decode_to_slice(known[Known::Sha256Sum as usize].as_ref(), &mut sha256sum) {
             return Err(ParseError::Decode { field: "SHA256SUM", err });
@@ -55,7 +59,7 @@ impl Desc {
             name: known[Known::Name as usize].clone(),
             filename: known[Known::Filename as usize].clone(),
             version: known[Known::Version as usize].clone(),
-            pgpsig: known[Known::PgpSig as usize].clone(),
+            pgpsig,
             builddate: match known[Known::BuildDate as usize].parse() {
                 Ok(secs) => SystemTime::UNIX_EPOCH + Duration::from_secs(secs),
                 Err(err) => return Err(ParseError::ParseInt { field: "BUILDDATE", err }),
@@ -67,6 +71,12 @@ impl Desc {
             },
         })
     }
+    pub fn fields(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.fields.iter().map(|(k, v)| (k.as_ref(), v.as_ref()))
+    }
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.fields.iter().find(|(k, _)| k.as_ref() == key).map(|(_, v)| v.as_ref())
+    }
     pub fn write_to(&self, dst: impl Write) -> std::io::Result<()> {
         writer::write(dst, self.fields.iter().map(|(k, v)| (&**k, &**v)))
     }
@@ -77,3 +87,23 @@ impl Desc {
     }
 }
 
+
+#[cfg(test)]
+impl Desc {
+    /// A package with just the fields every desc needs, plus `extra` ones like `DEPENDS`.
+    pub fn test(name: &str, version: &str, extra: &[(&str, &str)]) -> Arc<Desc> {
+        let fields = [
+            ("FILENAME", format!("{name}-{version}-x86_64.pkg.tar.zst")),
+            ("NAME", name.into()),
+            ("VERSION", version.into()),
+            ("CSIZE", "1".into()),
+            ("SHA256SUM", "00".repeat(32)),
+            ("BUILDDATE", "0".into()),
+        ];
+        let fields = fields.into_iter()
+            .map(|(k, v)| (k.into(), v.into()))
+            .chain(extra.iter().map(|(k, v)| ((*k).into(), (*v).into())))
+            .collect();
+        Arc::new(Desc::from_fields(fields).unwrap())
+    }
+}
@@ -0,0 +1,238 @@
+use std::{collections::{HashMap, HashSet, VecDeque}, fmt::Write, sync::Arc};
+
+use serde::Serialize;
+
+use crate::{database::{constraint::{split_dep, Constraint}, desc::Desc, search::SearchIndex}, Database};
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    Depends, OptDepends, MakeDepends, Replaces,
+}
+
+const FIELDS: [(&str, Kind); 4] = [
+    ("DEPENDS", Kind::Depends),
+    ("OPTDEPENDS", Kind::OptDepends),
+    ("MAKEDEPENDS", Kind::MakeDepends),
+    ("REPLACES", Kind::Replaces),
+];
+
+/// The graph and the search indexes of the states it was built from,
+/// which are replaced on every refresh.
+pub type Built = Option<(Vec<Arc<SearchIndex>>, Arc<DepGraph>)>;
+
+pub struct Node {
+    pub repo: Arc<str>,
+    pub desc: Arc<Desc>,
+}
+
+pub struct Edge {
+    pub kind: Kind,
+    /// As written in the desc, e.g. `glibc>=2.38`.
+    pub dep: Box<str>,
+    /// The package satisfying it, if any does.
+    pub to: Option<usize>,
+}
+
+/// Dependencies between the packages served by every repo, with provides resolved.
+#[derive(Default)]
+pub struct DepGraph {
+    pub nodes: Vec<Node>,
+    by_name: HashMap<(Arc<str>, Arc<str>), usize>,
+    pub forward: Vec<Vec<Edge>>,
+    /// Package and edge index in `forward` of everything pointing at a package.
+    pub reverse: Vec<Vec<(usize, usize)>>,
+}
+
+impl Kind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Kind::Depends => "depends",
+            Kind::OptDepends => "optdepends",
+            Kind::MakeDepends => "makedepends",
+            Kind::Replaces => "replaces",
+        }
+    }
+}
+
+/// Whether something providing `version` (`None` for an unversioned provide) satisfies `constraint`.
+fn satisfies(constraint: Option<&Constraint>, version: Option<&str>) -> bool {
+    match (constraint, version) {
+        (None, _) => true,
+        (Some(constraint), Some(version)) => constraint.matches(version),
+        (Some(_), None) => false,
+    }
+}
+
+impl DepGraph {
+    /// Packages are resolved in the order of `repos`, like pacman does.
+    pub fn new(repos: Vec<(Arc<str>, Vec<Arc<Desc>>)>) -> Self {
+        let mut graph = Self::default();
+        let mut providers = HashMap::<Box<str>, Vec<(usize, Option<Box<str>>)>>::new();
+        for (repo, descs) in repos {
+            for desc in descs {
+                let id = graph.nodes.len();
+                graph.by_name.insert((repo.clone(), desc.name.clone()), id);
+                providers.entry(desc.name.as_ref().into()).or_default().push((id, Some(desc.version.as_ref().into())));
+                for provide in desc.get("PROVIDES").into_iter().flat_map(|v| v.lines()) {
+                    let (name, version) = match provide.split_once('=') {
+                        Some((name, version)) => (name, Some(version.into())),
+                        None => (provide, None),
+                    };
+                    providers.entry(name.into()).or_default().push((id, version));
+                }
+                graph.nodes.push(Node { repo: repo.clone(), desc });
+            }
+        }
+        for node in graph.nodes.iter() {
+            let mut edges = Vec::new();
+            for (field, kind) in FIELDS {
+                for dep in node.desc.get(field).into_iter().flat_map(|v| v.lines()) {
+                    let (name, constraint) = split_dep(dep);
+                    let candidates = providers.get(name).into_iter().flatten()
+                        .filter(|(_, version)| satisfies(constraint.as_ref(), version.as_deref()))
+                        .map(|v| v.0);
+                    // a package's own name wins over other packages providing it
+                    let to = candidates.clone().find(|id| graph.nodes[*id].desc.name.as_ref() == name)
+                        .or_else(|| candidates.clone().next());
+                    edges.push(Edge { kind, dep: dep.into(), to });
+                }
+            }
+            graph.forward.push(edges);
+        }
+        graph.reverse = vec![Vec::new(); graph.nodes.len()];
+        for (from, edges) in graph.forward.iter().enumerate() {
+            for (idx, edge) in edges.iter().enumerate() {
+                if let Some(to) = edge.to {
+                    graph.reverse[to].push((from, idx));
+                }
+            }
+        }
+        graph
+    }
+    pub fn find(&self, repo: &str, name: &str) -> Option<usize> {
+        self.by_name.get(&(Arc::from(repo), Arc::from(name))).copied()
+    }
+    /// Packages reachable from `root` through `depends` edges, or pointing at it
+    /// if `reverse`, with their distance. `root` itself is first.
+    pub fn walk(&self, root: usize, reverse: bool, depth: usize) -> Vec<(usize, usize)> {
+        let mut seen = HashSet::from([root]);
+        let mut queue = VecDeque::from([(root, 0)]);
+        let mut found = Vec::new();
+        while let Some((id, dist)) = queue.pop_front() {
+            found.push((id, dist));
+            if dist >= depth {
+                continue;
+            }
+            let next: Vec<usize> = match reverse {
+                false => self.forward[id].iter().filter(|v| v.kind == Kind::Depends).filter_map(|v| v.to).collect(),
+                true => self.reverse[id].iter().filter(|(from, idx)| self.forward[*from][*idx].kind == Kind::Depends).map(|v| v.0).collect(),
+            };
+            for next in next {
+                if seen.insert(next) {
+                    queue.push_back((next, dist + 1));
+                }
+            }
+        }
+        found
+    }
+    /// The part of the graph [`DepGraph::walk`] reaches, in Graphviz DOT.
+    pub fn dot(&self, root: usize, reverse: bool, depth: usize) -> String {
+        let found = self.walk(root, reverse, depth);
+        let ids = found.iter().map(|v| v.0).collect::<HashSet<_>>();
+        let mut dst = String::from("digraph deps {\n    rankdir=LR;\n    node [shape=box];\n");
+        for &(id, _) in found.iter() {
+            let node = &self.nodes[id];
+            let style = if id == root { ", style=bold" } else { "" };
+            _ = writeln!(dst, "    n{id} [label=\"{}/{}\\n{}\"{style}];", node.repo, node.desc.name, node.desc.version);
+        }
+        for &(from, _) in found.iter() {
+            for edge in self.forward[from].iter().filter(|v| v.kind == Kind::Depends) {
+                if let Some(to) = edge.to.filter(|v| ids.contains(v)) {
+                    _ = writeln!(dst, "    n{from} -> n{to};");
+                }
+            }
+        }
+        dst.push_str("}\n");
+        dst
+    }
+}
+
+impl Database {
+    /// The graph of what's served now, rebuilt on first use after a refresh.
+    pub fn graph(&self) -> Arc<DepGraph> {
+        let repos = self.config.repo_names()
+            .filter_map(|name| self.repos.get(name))
+            .collect::<Vec<_>>();
+        let indexes = repos.iter().map(|v| v.search.read().unwrap().clone()).collect::<Vec<_>>();
+        let mut graph = self.graph.lock().unwrap();
+        if let Some((built, graph)) = graph.as_ref()
+            && built.len() == indexes.len()
+            && built.iter().zip(indexes.iter()).all(|(a, b)| Arc::ptr_eq(a, b)) {
+            return graph.clone();
+        }
+        let built = Arc::new(DepGraph::new(repos.iter().map(|repo| {
+            let state = repo.state.read().unwrap();
+            let mut descs = Vec::from_iter(state.packages.values().map(|v| v.desc.clone()));
+            descs.sort_by(|a, b| a.name.cmp(&b.name));
+            (repo.name.clone(), descs)
+        }).collect()));
+        *graph = Some((indexes, built.clone()));
+        built
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph(repos: &[(&str, Vec<Arc<Desc>>)]) -> DepGraph {
+        DepGraph::new(repos.iter().map(|(repo, descs)| (Arc::from(*repo), descs.clone())).collect())
+    }
+
+    fn target<'a>(graph: &'a DepGraph, repo: &str, name: &str) -> Vec<Option<(&'a str, &'a str)>> {
+        graph.forward[graph.find(repo, name).unwrap()].iter()
+            .map(|edge| edge.to.map(|id| (graph.nodes[id].repo.as_ref(), graph.nodes[id].desc.name.as_ref())))
+            .collect()
+    }
+
+    #[test]
+    fn resolves_provides() {
+        let graph = graph(&[("core", vec![
+            Desc::test("app", "1", &[("DEPENDS", "sh\nlibfoo.so=2-64\nmissing")]),
+            Desc::test("bash", "5.2", &[("PROVIDES", "sh")]),
+            Desc::test("foo", "2", &[("PROVIDES", "libfoo.so=2-64")]),
+        ])]);
+        assert_eq!(target(&graph, "core", "app"), [Some(("core", "bash")), Some(("core", "foo")), None]);
+        assert_eq!(graph.reverse[graph.find("core", "bash").unwrap()], [(graph.find("core", "app").unwrap(), 0)]);
+    }
+
+    #[test]
+    fn checks_constraints() {
+        let graph = graph(&[("core", vec![
+            Desc::test("app", "1", &[("DEPENDS", "glibc>=2.38\nsh>=5\nold<2")]),
+            Desc::test("glibc", "2.37", &[]),
+            Desc::test("bash", "5.2", &[("PROVIDES", "sh")]),
+            Desc::test("old", "1", &[]),
+        ])]);
+        // an unversioned provide doesn't satisfy a versioned dependency
+        assert_eq!(target(&graph, "core", "app"), [None, None, Some(("core", "old"))]);
+    }
+
+    #[test]
+    fn prefers_the_name_then_the_repo_order() {
+        let graph = graph(&[
+            ("core", vec![
+                Desc::test("app", "1", &[("DEPENDS", "sh\nlib")]),
+                Desc::test("busybox", "1", &[("PROVIDES", "sh")]),
+            ]),
+            ("extra", vec![
+                Desc::test("sh", "1", &[]),
+                Desc::test("lib", "1", &[]),
+            ]),
+            ("more", vec![Desc::test("lib", "2", &[])]),
+        ]);
+        assert_eq!(target(&graph, "core", "app"), [Some(("extra", "sh")), Some(("extra", "lib"))]);
+    }
+}
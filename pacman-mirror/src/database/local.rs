@@ -0,0 +1,184 @@
+use std::{collections::HashMap, fs::File, io::{ErrorKind, Read}, path::{Path, PathBuf}, process::{Command, Stdio}, sync::{Arc, Mutex}, time::SystemTime};
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{config::Secret, database::{desc::Desc, local::package_file::{sig_path, Compression}}};
+
+pub mod package_file;
+
+
+/// A repository backed by a directory of our own `.pkg.tar.*` and `.sig` files.
+#[derive(Debug,Serialize,Deserialize,Clone)]
+pub struct LocalRepo {
+    pub name: Arc<str>,
+    pub path: PathBuf,
+    /// Upstream repos whose packages are replaced by the ones in this repo.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub overlays: Vec<Arc<str>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub upload_tokens: Vec<Secret>,
+    /// Keyring that `gpgv` checks uploaded signatures against. Without one,
+    /// signatures are only checked to be well formed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keyring: Option<PathBuf>,
+}
+
+#[derive(Debug,Error)]
+pub enum AddError {
+    #[error("Invalid package: {0}")]
+    Invalid(anyhow::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// Removes temporary upload files that didn't get moved into place.
+struct TempFiles([PathBuf; 2]);
+
+impl Drop for TempFiles {
+    fn drop(&mut self) {
+        for path in self.0.iter() {
+            _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+struct Known {
+    modified: SystemTime,
+    len: u64,
+    desc: Arc<Desc>,
+}
+
+/// Builds the package list of a [`LocalRepo`] by reading every package in its directory.
+/// Packages are only re-read when their size or modification time changes.
+pub struct LocalIndex {
+    pub dir: PathBuf,
+    known: Mutex<HashMap<PathBuf, Known>>,
+    write_lock: Mutex<()>,
+}
+
+/// Checks the detached signature `{package}.sig` with `gpgv`.
+fn verify_signature(keyring: &Path, package: &Path) -> Result<(), AddError> {
+    let output = Command::new("gpgv")
+        .arg("--keyring").arg(keyring)
+        .arg(sig_path(package)).arg(package)
+        .stdin(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        let err = String::from_utf8_lossy(&output.stderr);
+        return Err(AddError::Invalid(anyhow::anyhow!("signature does not verify: {}", err.trim())));
+    }
+    Ok(())
+}
+
+pub fn is_package(name: &str) -> bool {
+    name.contains(".pkg.tar") && !name.ends_with(".sig") && !name.starts_with('.')
+}
+
+impl LocalIndex {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir, known: Mutex::default(), write_lock: Mutex::default() }
+    }
+    fn read(&self, path: &Path) -> anyhow::Result<Arc<Desc>> {
+        let meta = std::fs::metadata(path)?;
+        let sig_modified = std::fs::metadata(package_file::sig_path(path)).and_then(|v| v.modified()).ok();
+        let modified = meta.modified()?.max(sig_modified.unwrap_or(SystemTime::UNIX_EPOCH));
+        if let Some(known) = self.known.lock().unwrap().get(path)
+            && known.modified == modified && known.len == meta.len() {
+            return Ok(known.desc.clone());
+        }
+        let filename = path.file_name().unwrap_or_default().to_string_lossy();
+        let desc = Arc::new(package_file::read_desc(path, &filename)?);
+        self.known.lock().unwrap().insert(path.into(), Known { modified, len: meta.len(), desc: desc.clone() });
+        Ok(desc)
+    }
+    pub fn scan(&self) -> std::io::Result<Vec<Arc<Desc>>> {
+        let mut paths = Vec::new();
+        for entry in std::fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() && is_package(&entry.file_name().to_string_lossy()) {
+                paths.push(entry.path());
+            }
+        }
+        paths.sort();
+
+        let mut descs = Vec::with_capacity(paths.len());
+        for path in paths.iter() {
+            match self.read(path) {
+                Ok(desc) => descs.push(desc),
+                Err(err) => warn!("Skipping {}: {err:?}", path.display()),
+            }
+        }
+        self.known.lock().unwrap().retain(|path, _| paths.contains(path));
+        Ok(descs)
+    }
+    fn remove_files(&self, path: &Path) -> std::io::Result<()> {
+        for path in [sig_path(path), path.into()] {
+            match std::fs::remove_file(&path) {
+                Err(err) if err.kind() != ErrorKind::NotFound => return Err(err),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+    /// Stores a package in the repo directory, replacing any other versions
+    /// of it the way `repo-add -R` does. The new list of packages goes to
+    /// `publish` before the older versions are deleted.
+    pub fn add(&self, mut src: impl Read, sig: Option<&[u8]>, arch: &str, keyring: Option<&Path>, publish: impl FnOnce(Vec<Arc<Desc>>)) -> Result<Arc<Desc>, AddError> {
+        let _lock = self.write_lock.lock().unwrap();
+        let tmp = self.dir.join(format!(".upload-{:016x}", rand::random::<u64>()));
+        let _cleanup = TempFiles([tmp.clone(), sig_path(&tmp)]);
+
+        std::io::copy(&mut src, &mut File::create(&tmp)?)?;
+        if let Some(sig) = sig {
+            package_file::check_signature(sig).map_err(AddError::Invalid)?;
+            std::fs::write(sig_path(&tmp), sig)?;
+            if let Some(keyring) = keyring {
+                verify_signature(keyring, &tmp)?;
+            }
+        }
+        let compression = Compression::detect(&tmp).map_err(AddError::Invalid)?;
+        let desc = package_file::read_desc(&tmp, "").map_err(AddError::Invalid)?;
+        let pkg_arch = desc.get("ARCH").unwrap_or_default();
+
+        if pkg_arch != arch && pkg_arch != "any" {
+            return Err(AddError::Invalid(anyhow::anyhow!("architecture {pkg_arch:?} does not match {arch:?}")));
+        }
+        let filename = format!("{}-{}-{pkg_arch}.pkg.tar{}", desc.name, desc.version, compression.extension());
+        if filename.contains('/') || !is_package(&filename) {
+            return Err(AddError::Invalid(anyhow::anyhow!("invalid file name {filename:?}")));
+        }
+        let path = self.dir.join(&filename);
+        self.remove_files(&path)?;
+        if sig.is_some() {
+            std::fs::rename(sig_path(&tmp), sig_path(&path))?;
+        }
+        std::fs::rename(&tmp, &path)?;
+        let desc = self.read(&path).map_err(AddError::Invalid)?;
+
+        let (old, packages) = self.scan()?.into_iter()
+            .partition::<Vec<_>, _>(|v| v.name == desc.name && v.filename != desc.filename);
+        publish(packages);
+        for old in old {
+            info!("Replacing {} with {filename}", old.filename);
+            self.remove_files(&self.dir.join(old.filename.as_ref()))?;
+        }
+        Ok(desc)
+    }
+    /// Removes every version of a package, returning how many files were removed.
+    /// The remaining packages go to `publish` before anything is deleted.
+    pub fn remove(&self, name: &str, publish: impl FnOnce(Vec<Arc<Desc>>)) -> anyhow::Result<usize> {
+        let _lock = self.write_lock.lock().unwrap();
+        let (removed, packages) = self.scan()?.into_iter()
+            .partition::<Vec<_>, _>(|v| v.name.as_ref() == name);
+        if removed.is_empty() {
+            return Ok(0);
+        }
+        publish(packages);
+        for desc in removed.iter() {
+            self.remove_files(&self.dir.join(desc.filename.as_ref()))?;
+        }
+        Ok(removed.len())
+    }
+}
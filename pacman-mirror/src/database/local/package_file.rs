@@ -0,0 +1,223 @@
+use std::{collections::HashMap, fs::File, io::{BufReader, Read}, path::{Path, PathBuf}, sync::Arc};
+
+use anyhow::{anyhow, bail};
+use base64::{prelude::BASE64_STANDARD, Engine};
+use sha2::Digest;
+
+use crate::database::desc::{writer, Desc};
+
+
+/// `.PKGINFO` keys and the desc fields they become, in `repo-add` order.
+/// The fields without a key are computed from the package file.
+const FIELDS: [(&str, Option<&str>); 22] = [
+    ("FILENAME", None),
+    ("NAME", Some("pkgname")),
+    ("BASE", Some("pkgbase")),
+    ("VERSION", Some("pkgver")),
+    ("DESC", Some("pkgdesc")),
+    ("GROUPS", Some("group")),
+    ("CSIZE", None),
+    ("ISIZE", Some("size")),
+    ("SHA256SUM", None),
+    ("PGPSIG", None),
+    ("URL", Some("url")),
+    ("LICENSE", Some("license")),
+    ("ARCH", Some("arch")),
+    ("BUILDDATE", Some("builddate")),
+    ("PACKAGER", Some("packager")),
+    ("REPLACES", Some("replaces")),
+    ("CONFLICTS", Some("conflict")),
+    ("PROVIDES", Some("provides")),
+    ("DEPENDS", Some("depend")),
+    ("OPTDEPENDS", Some("optdepend")),
+    ("MAKEDEPENDS", Some("makedepend")),
+    ("CHECKDEPENDS", Some("checkdepend")),
+];
+
+pub fn sig_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".sig");
+    name.into()
+}
+
+/// Checks `sig` is a binary OpenPGP detached signature, as pacman expects in `.sig` files.
+pub fn check_signature(mut sig: &[u8]) -> anyhow::Result<()> {
+    if sig.starts_with(b"-----BEGIN PGP SIGNATURE") {
+        bail!("armored signatures are not supported, send the binary .sig file");
+    }
+    if sig.is_empty() {
+        bail!("empty signature");
+    }
+    while let [header, rest @ ..] = sig {
+        if header & 0x80 == 0 {
+            bail!("not an OpenPGP signature");
+        }
+        // new format packets keep the tag in the low 6 bits, old ones in bits 2-5
+        let (tag, len, rest) = if header & 0x40 != 0 {
+            match rest {
+                [a @ 0..192, rest @ ..] => (header & 0x3f, *a as usize, rest),
+                [a @ 192..224, b, rest @ ..] => (header & 0x3f, ((*a as usize - 192) << 8) + *b as usize + 192, rest),
+                [255, a, b, c, d, rest @ ..] => (header & 0x3f, u32::from_be_bytes([*a, *b, *c, *d]) as usize, rest),
+                _ => bail!("unsupported OpenPGP packet length"),
+            }
+        } else {
+            match (header & 0x03, rest) {
+                (0, [a, rest @ ..]) => ((header >> 2) & 0x0f, *a as usize, rest),
+                (1, [a, b, rest @ ..]) => ((header >> 2) & 0x0f, u16::from_be_bytes([*a, *b]) as usize, rest),
+                (2, [a, b, c, d, rest @ ..]) => ((header >> 2) & 0x0f, u32::from_be_bytes([*a, *b, *c, *d]) as usize, rest),
+                _ => bail!("unsupported OpenPGP packet length"),
+            }
+        };
+        let Some(body) = rest.get(..len) else {
+            bail!("truncated OpenPGP packet");
+        };
+        if tag != 2 {
+            bail!("expected a signature, found OpenPGP packet type {tag}");
+        }
+        // v3 signatures have a length byte before the signature type
+        let ty = match body {
+            [3, _, ty, ..] | [4..=6, ty, ..] => *ty,
+            _ => bail!("unsupported signature version"),
+        };
+        if ty != 0x00 {
+            bail!("expected a signature of a binary document, found type {ty:#04x}");
+        }
+        sig = &rest[len..];
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Compression {
+    None, Gzip, Xz, Zstd,
+}
+
+impl Compression {
+    pub fn detect(path: &Path) -> anyhow::Result<Self> {
+        let mut magic = [0u8; 512];
+        let len = File::open(path)?.read(&mut magic)?;
+        let magic = &magic[..len];
+        Ok(if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Self::Zstd
+        } else if magic.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Self::Xz
+        } else if magic.starts_with(&[0x1f, 0x8b]) {
+            Self::Gzip
+        } else if magic.get(257..262) == Some(b"ustar") {
+            Self::None
+        } else {
+            bail!("not a supported package archive, expected a .pkg.tar, .pkg.tar.gz, .pkg.tar.xz or .pkg.tar.zst");
+        })
+    }
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::None => "",
+            Self::Gzip => ".gz",
+            Self::Xz => ".xz",
+            Self::Zstd => ".zst",
+        }
+    }
+}
+
+pub fn open_archive(path: &Path) -> anyhow::Result<tar::Archive<Box<dyn Read>>> {
+    let compression = Compression::detect(path)?;
+    let file = BufReader::new(File::open(path)?);
+    let src: Box<dyn Read> = match compression {
+        Compression::Zstd => Box::new(zstd::Decoder::with_buffer(file)?),
+        Compression::Xz => Box::new(xz2::bufread::XzDecoder::new(file)),
+        Compression::Gzip => Box::new(flate2::bufread::GzDecoder::new(file)),
+        Compression::None => Box::new(file),
+    };
+    Ok(tar::Archive::new(src))
+}
+
+fn parse_pkginfo(src: &str) -> HashMap<&str, Vec<&str>> {
+    let mut values = HashMap::<&str, Vec<&str>>::new();
+    for line in src.lines() {
+        let line = line.trim();
+        if line.starts_with('#') {
+            continue;
+        }
+        if let Some((k, v)) = line.split_once(" = ") {
+            values.entry(k.trim()).or_default().push(v.trim());
+        }
+    }
+    values
+}
+
+/// Builds the desc and file list of a package the way `repo-add` would.
+pub fn read_desc(path: &Path, filename: &str) -> anyhow::Result<Desc> {
+    let mut hasher = sha2::Sha256::new();
+    let csize = std::io::copy(&mut File::open(path)?, &mut hasher)?;
+    let sha256sum = hex::encode(hasher.finalize());
+
+    let pgpsig = match std::fs::read(sig_path(path)) {
+        Ok(data) => Some(BASE64_STANDARD.encode(data)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut pkginfo = None;
+    let mut files = Vec::new();
+    let mut archive = open_archive(path)?;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let entry_path = String::from_utf8_lossy(&entry.path_bytes()).into_owned();
+        if entry_path == ".PKGINFO" {
+            let mut str = String::new();
+            entry.read_to_string(&mut str)?;
+            pkginfo = Some(str);
+        } else if !entry_path.starts_with('.') {
+            files.push(entry_path);
+        }
+    }
+    let pkginfo = pkginfo.ok_or_else(|| anyhow!("{filename} has no .PKGINFO"))?;
+    let pkginfo = parse_pkginfo(&pkginfo);
+    files.sort();
+
+    let mut fields = Vec::<(Arc<str>, Arc<str>)>::new();
+    for (key, src) in FIELDS {
+        let value = match (key, src) {
+            ("FILENAME", _) => filename.to_string(),
+            ("CSIZE", _) => csize.to_string(),
+            ("SHA256SUM", _) => sha256sum.clone(),
+            ("PGPSIG", _) => match &pgpsig {
+                Some(v) => v.clone(),
+                None => continue,
+            },
+            (_, Some(src)) => match pkginfo.get(src) {
+                Some(v) => v.join("\n"),
+                None => continue,
+            },
+            (_, None) => continue,
+        };
+        fields.push((key.into(), value.into()));
+    }
+    let mut desc = Desc::from_fields(fields)?;
+
+    let mut files_data = Vec::new();
+    writer::write(&mut files_data, [("FILES", files.join("\n").as_str())])?;
+    desc.files = Some(String::from_utf8(files_data)?.into());
+    Ok(desc)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signatures() {
+        // new and old format headers around the start of a v4 signature body
+        assert!(check_signature(&[0xc2, 4, 4, 0x00, 1, 8]).is_ok());
+        assert!(check_signature(&[0x88, 4, 4, 0x00, 1, 8]).is_ok());
+        assert!(check_signature(&[0x89, 0, 4, 4, 0x00, 1, 8, 0xc2, 3, 3, 5, 0x00]).is_ok());
+
+        assert!(check_signature(b"").is_err());
+        assert!(check_signature(b"-----BEGIN PGP SIGNATURE-----\n").is_err());
+        assert!(check_signature(b"not a signature").is_err());
+        // truncated, a text document, and a public key packet
+        assert!(check_signature(&[0xc2, 8, 4, 0x00]).is_err());
+        assert!(check_signature(&[0xc2, 2, 4, 0x01]).is_err());
+        assert!(check_signature(&[0xc6, 2, 4, 0x00]).is_err());
+    }
+}
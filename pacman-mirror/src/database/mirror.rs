@@ -1,20 +1,152 @@
+use std::{collections::BTreeMap, time::Duration};
+
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{config::{duration, Secret}, Config};
+
 
-use crate::Config;
+const PLACEHOLDERS: [&str; 3] = ["$repo", "$name", "$arch"];
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(60);
+const DEFAULT_DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(3600);
 
+/// An upstream mirror and the settings used when talking to it.
+///
+/// In the config this is either a plain URL string, or a table with a `url`
+/// and any of the optional settings below. `file://` URLs are read straight
+/// from disk and ignore the connection settings.
+#[derive(Debug,Serialize,Deserialize,Eq,Hash,PartialEq,Clone)]
+#[serde(from = "MirrorRepr")]
+pub struct Mirror {
+    pub url: Box<str>,
+    /// How long to wait for a response to start, 30 seconds by default.
+    #[serde(with = "duration::option", skip_serializing_if = "Option::is_none")]
+    pub connect_timeout: Option<Duration>,
+    /// How long a response may send nothing before it's dropped, 60 seconds by default.
+    #[serde(with = "duration::option", skip_serializing_if = "Option::is_none")]
+    pub read_timeout: Option<Duration>,
+    /// How long a whole request may take, body included, 1 hour by default.
+    #[serde(with = "duration::option", skip_serializing_if = "Option::is_none")]
+    pub download_timeout: Option<Duration>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<Box<str>, Box<str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auth: Option<Auth>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<Box<str>>,
+}
 
 #[derive(Debug,Serialize,Deserialize,Eq,Hash,PartialEq,Clone)]
-pub struct Mirror(Box<str>);
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Auth {
+    Basic { username: Box<str>, password: Secret },
+    Bearer { token: Secret },
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MirrorRepr {
+    Url(Box<str>),
+    Full {
+        url: Box<str>,
+        #[serde(default, with = "duration::option")]
+        connect_timeout: Option<Duration>,
+        #[serde(default, with = "duration::option")]
+        read_timeout: Option<Duration>,
+        #[serde(default, with = "duration::option")]
+        download_timeout: Option<Duration>,
+        #[serde(default)]
+        headers: BTreeMap<Box<str>, Box<str>>,
+        #[serde(default)]
+        auth: Option<Auth>,
+        #[serde(default)]
+        proxy: Option<Box<str>>,
+    },
+}
+
+impl From<MirrorRepr> for Mirror {
+    fn from(value: MirrorRepr) -> Self {
+        match value {
+            MirrorRepr::Url(url) => Mirror::new(url),
+            MirrorRepr::Full { url, connect_timeout, read_timeout, download_timeout, headers, auth, proxy } => {
+                Mirror { url, connect_timeout, read_timeout, download_timeout, headers, auth, proxy }
+            }
+        }
+    }
+}
+
+#[derive(Debug,Error)]
+pub enum MirrorProblem {
+    #[error("unsupported URL scheme, expected http://, https:// or file://")]
+    Scheme,
+    #[error("missing $repo placeholder, e.g. https://example.com/$repo/os/$arch/")]
+    MissingRepo,
+    #[error("unknown placeholder {0}, expected one of $repo, $name or $arch")]
+    UnknownPlaceholder(Box<str>),
+    #[error("proxy {0:?} is not an http:// URL")]
+    Proxy(Box<str>),
+    #[error("{0} must be greater than zero")]
+    ZeroTimeout(&'static str),
+}
 
 impl Mirror {
-    pub fn new(path: Box<str>) -> Self {
-        Self(path)
+    pub fn new(url: Box<str>) -> Self {
+        Self {
+            url,
+            connect_timeout: None,
+            read_timeout: None,
+            download_timeout: None,
+            headers: BTreeMap::new(),
+            auth: None,
+            proxy: None,
+        }
+    }
+    pub fn connect_timeout(&self) -> Duration {
+        self.connect_timeout.unwrap_or(DEFAULT_CONNECT_TIMEOUT)
+    }
+    pub fn read_timeout(&self) -> Duration {
+        self.read_timeout.unwrap_or(DEFAULT_READ_TIMEOUT)
+    }
+    pub fn download_timeout(&self) -> Duration {
+        self.download_timeout.unwrap_or(DEFAULT_DOWNLOAD_TIMEOUT)
+    }
+    pub fn url(&self) -> &str {
+        &self.url
     }
     pub fn get(&self, config: &Config, repo: &str) -> String {
-        self.0
+        self.url
             .replace("$repo", repo)
             .replace("$name", &config.name)
             .replace("$arch", &config.arch)
     }
+    pub fn problems(&self) -> Vec<MirrorProblem> {
+        let mut problems = Vec::new();
+        if !["http://", "https://", "file://"].iter().any(|v| self.url.starts_with(v)) {
+            problems.push(MirrorProblem::Scheme);
+        }
+        if !self.url.contains("$repo") {
+            problems.push(MirrorProblem::MissingRepo);
+        }
+        for (idx, _) in self.url.match_indices('$') {
+            let rest = &self.url[idx..];
+            let len = rest[1..].find(|c: char| !c.is_ascii_alphanumeric() && c != '_').map_or(rest.len(), |v| v + 1);
+            if !PLACEHOLDERS.contains(&&rest[..len]) {
+                problems.push(MirrorProblem::UnknownPlaceholder(rest[..len].into()));
+            }
+        }
+        if let Some(proxy) = self.proxy.as_ref().filter(|v| !v.starts_with("http://")) {
+            problems.push(MirrorProblem::Proxy(proxy.clone()));
+        }
+        if self.connect_timeout.is_some_and(|v| v.is_zero()) {
+            problems.push(MirrorProblem::ZeroTimeout("connect_timeout"));
+        }
+        if self.read_timeout.is_some_and(|v| v.is_zero()) {
+            problems.push(MirrorProblem::ZeroTimeout("read_timeout"));
+        }
+        if self.download_timeout.is_some_and(|v| v.is_zero()) {
+            problems.push(MirrorProblem::ZeroTimeout("download_timeout"));
+        }
+        problems
+    }
 }
-
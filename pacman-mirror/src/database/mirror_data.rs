@@ -1,8 +1,8 @@
-use std::sync::{Arc, RwLock};
+use std::{path::Path, sync::{atomic::{AtomicBool, Ordering}, Arc, RwLock}, time::Instant};
 
 use replay_buffer::{ReplayBuffer, ReplayBufferWriter};
 
-use crate::{database::{desc::Desc, mirror::Mirror}, Config};
+use crate::{database::{desc::Desc, local::{LocalIndex, LocalRepo}, mirror::Mirror}, http, metrics::{self, MirrorMetrics}, Config};
 
 mod update;
 
@@ -12,21 +12,66 @@ pub struct State {
 }
 
 pub struct MirrorData {
+    pub mirror: Mirror,
     pub repo_name: Arc<str>,
     pub repo_url: Arc<str>,
+    /// Shared by the local repo and every repo it overlays, each with its own `state`.
+    pub local: Option<Arc<LocalIndex>>,
     pub state: RwLock<State>,
+    /// Set by an admin to stop using this mirror for refreshes and downloads.
+    quarantined: AtomicBool,
+    pub metrics: MirrorMetrics,
 }
 
 impl MirrorData {
     pub fn new(config: &Config, mirror: &Mirror, repo_name: Arc<str>) -> Self {
         let repo_url: Arc<str> = mirror.get(config, &repo_name).into();
         Self {
+            mirror: mirror.clone(),
             repo_name,
             repo_url,
+            local: None,
             state: RwLock::new(State {
                 packages: ReplayBufferWriter::new().source().clone(),
             }),
+            quarantined: AtomicBool::new(false),
+            metrics: MirrorMetrics::default(),
         }
     }
+    pub fn new_local(repo: &LocalRepo, index: Arc<LocalIndex>) -> Self {
+        let repo_url: Arc<str> = format!("file://{}", repo.path.display()).into();
+        Self {
+            mirror: Mirror::new(repo_url.as_ref().into()),
+            repo_name: repo.name.clone(),
+            repo_url,
+            local: Some(index),
+            state: RwLock::new(State {
+                packages: ReplayBufferWriter::new().source().clone(),
+            }),
+            quarantined: AtomicBool::new(false),
+            metrics: MirrorMetrics::default(),
+        }
+    }
+    pub fn restore(&self, packages: Arc<ReplayBuffer<Arc<Desc>>>) {
+        *self.state.write().unwrap() = State { packages };
+    }
+    /// Sends a GET request to `url` on this mirror, counting it in `metrics`.
+    pub fn get(&self, url: &str) -> anyhow::Result<http::Body> {
+        let start = Instant::now();
+        let res = http::get(&self.mirror, url);
+        metrics::inc(&self.metrics.requests, 1);
+        metrics::inc(&self.metrics.errors, res.is_err() as u64);
+        self.metrics.latency.observe(start.elapsed());
+        res
+    }
+    pub fn is_quarantined(&self) -> bool {
+        self.quarantined.load(Ordering::Relaxed)
+    }
+    pub fn set_quarantined(&self, quarantined: bool) {
+        self.quarantined.store(quarantined, Ordering::Relaxed);
+    }
+    pub fn local_path(&self) -> Option<&Path> {
+        self.repo_url.strip_prefix("file://").map(Path::new)
+    }
 }
 
@@ -1,99 +1,33 @@
-use std::{ffi::OsString, io::Read, path::Path, sync::Arc};
+use std::{fs::File, io::Read, path::Path, sync::Arc};
 
-use flate2::read::GzDecoder;
-use log::{debug, trace};
+use log::debug;
 use replay_buffer::ReplayBufferWriter;
 
-use crate::database::{desc::Desc, mirror_data::MirrorData, repo::state::FetchType};
+use crate::{database::{archive, desc::Desc, mirror_data::MirrorData, repo::state::FetchType}};
 
 
-struct PartialPackage {
-    name: OsString,
-    desc: Option<Desc>,
-    files: Option<Arc<str>>,
-}
-
-impl PartialPackage {
-    pub fn new(name: OsString) -> Self {
-        Self { name, desc: None, files: None }
-    }
-    pub fn into_desc(self, ty: FetchType) -> Option<Arc<Desc>> {
-        let mut desc = self.desc?;
-        if ty >= FetchType::Files {
-            desc.files = Some(self.files?);
-        }
-        Some(desc.into())
-    }
-}
-
 impl MirrorData {
-
-    pub fn prepare_for_update(&self) -> ReplayBufferWriter<Arc<Desc>> {
-        let repo_url = &self.repo_url;
-        trace!("Prepare for connection: {repo_url}");
-        let packages_writer = ReplayBufferWriter::new();
-        *self.state.write().unwrap() = super::State {
-            packages: packages_writer.source().clone(),
-        };
-        packages_writer
-    }
-
     pub fn update(&self, dst: &mut ReplayBufferWriter<Arc<Desc>>, fetch_ty: FetchType) -> anyhow::Result<()> {
+        if let Some(local) = &self.local {
+            dst.extend(local.scan()?);
+            return Ok(());
+        }
 
         let repo_url = self.repo_url.as_ref();
-        let db_url_path = Path::new(repo_url)
-            .join(format!("{}.{}", self.repo_name, match fetch_ty {
-                FetchType::Files => "files",
-                FetchType::Db => "db",
-            }));
-        let db_url = db_url_path.to_string_lossy();
-        let res = minreq::get(db_url.as_ref()).send_lazy()?;
+        let db_name = format!("{}.{}", self.repo_name, match fetch_ty {
+            FetchType::Files => "files",
+            FetchType::Db => "db",
+        });
+        let res: Box<dyn Read> = match self.local_path() {
+            Some(dir) => Box::new(File::open(dir.join(db_name))?),
+            None => {
+                let db_url_path = Path::new(repo_url).join(db_name);
+                Box::new(self.get(db_url_path.to_string_lossy().as_ref())?)
+            }
+        };
 
-        if res.status_code != 200 {
-            anyhow::bail!("Request {repo_url} failed with code {}: {}", res.status_code, res.reason_phrase);
-        }
-        
         debug!("Started connection: {repo_url}");
-
-        let mut partial_pkg = Option::<PartialPackage>::None;
-        let mut archive = tar::Archive::new(GzDecoder::new(res));
-
-        for entry in archive.entries()? {
-            let mut entry = entry?;
-            let path = entry.path()?.into_owned();
-            let mut path_iter = path.iter();
-
-            let name = match path_iter.next() {
-                None => continue,
-                Some(v) => v,
-            };
-            let ty = match path_iter.next() {
-                None => continue,
-                Some(v) => v,
-            };
-            let partial_pkg = partial_pkg.get_or_insert_with(|| PartialPackage::new(name.into()));
-            if partial_pkg.name != name {
-                let pkg = std::mem::replace(partial_pkg, PartialPackage::new(name.into()));
-                if let Some(desc) = pkg.into_desc(fetch_ty) {
-                    dst.push(desc);
-                }
-            }
-            if path_iter.next().is_some() {
-                continue;
-            }
-            let mut str = String::new();
-            if ty == "desc" {
-                entry.read_to_string(&mut str)?;
-                partial_pkg.desc = Some(Desc::parse(str.into())?);
-            }
-            else if ty == "files" {
-                entry.read_to_string(&mut str)?;
-                partial_pkg.files = Some(str.into());
-            }
-        }
-        if let Some(desc) = partial_pkg.take().and_then(|v| v.into_desc(fetch_ty)) {
-            dst.push(desc);
-        }
+        archive::read(res, fetch_ty, |desc| dst.push(desc))?;
         debug!("Wrapping up: {repo_url}");
         Ok(())
     }
@@ -2,19 +2,20 @@ use std::sync::Arc;
 
 use crate::cache::Cache;
 
-use super::desc::Desc;
+use super::{desc::Desc, mirror_data::MirrorData};
 
+#[derive(Clone)]
 pub struct Package {
     pub desc: Arc<Desc>,
-    pub cache: Cache,
-    pub mirrors: Vec<Arc<str>>,
+    pub cache: Arc<Cache>,
+    pub mirrors: Vec<Arc<MirrorData>>,
 }
 
 impl Package {
     pub fn new(desc: Arc<Desc>) -> Self {
         Self {
             desc,
-            cache: Cache::new(),
+            cache: Arc::new(Cache::new()),
             mirrors: Vec::new(),
         }
     }
@@ -0,0 +1,211 @@
+use std::{collections::{HashMap, VecDeque}, sync::{Arc, Condvar, Mutex}, time::{Duration, Instant}};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{cache::DataSource, database::{package::Package, repo::{diff::Diff, State}}, date::DateTime, glob, Config};
+
+
+/// How many finished downloads are kept for the prefetch page.
+const HISTORY: usize = 100;
+
+/// Downloads packages into the cache before any client asks for them.
+///
+/// ```toml
+/// [prefetch]
+/// updates = true
+/// watch = ["linux", "core/linux-firmware*"]
+/// concurrency = 2
+/// max_rate = 10485760
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PrefetchConfig {
+    /// Fetch the new version of a package when a refresh upgrades one that was cached.
+    pub updates: bool,
+    /// Packages fetched after every refresh, as `name` or `repo/name` patterns.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub watch: Vec<Box<str>>,
+    /// Downloads running at once.
+    pub concurrency: usize,
+    /// Bytes per second prefetching may download, or 0 for no limit.
+    /// Clients asking for a package being prefetched get it at this rate too.
+    pub max_rate: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Reason {
+    /// A newer version of a cached package.
+    Update,
+    Watch,
+    /// Asked for through `POST /api/v1/plan?warm=true`.
+    Plan,
+    /// Asked for through `POST /admin/prefetch`.
+    Admin,
+}
+
+pub struct Job {
+    pub repo: Arc<str>,
+    pub package: Package,
+    pub reason: Reason,
+}
+
+#[derive(Debug, Clone)]
+pub enum Outcome {
+    Done,
+    /// Already cached by the time the job started.
+    Cached,
+    /// No longer served by the time the job started.
+    Removed,
+    Failed(Box<str>),
+}
+
+pub struct Finished {
+    pub repo: Arc<str>,
+    pub filename: Arc<str>,
+    pub reason: Reason,
+    pub size: usize,
+    pub outcome: Outcome,
+    pub time: DateTime,
+}
+
+#[derive(Default)]
+pub struct Progress {
+    pub queued: VecDeque<Job>,
+    pub active: Vec<(Arc<str>, Arc<str>, Reason)>,
+    /// Newest first.
+    pub finished: VecDeque<Finished>,
+    pub done: usize,
+    pub failed: usize,
+    pub bytes: u64,
+}
+
+pub struct Prefetcher {
+    progress: Mutex<Progress>,
+    ready: Condvar,
+    /// When the next chunk may be read to stay within `max_rate`.
+    next_read: Mutex<Instant>,
+    config: PrefetchConfig,
+}
+
+impl Default for PrefetchConfig {
+    fn default() -> Self {
+        Self { updates: false, watch: Vec::new(), concurrency: 2, max_rate: 0 }
+    }
+}
+
+impl Reason {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Reason::Update => "update",
+            Reason::Watch => "watch",
+            Reason::Plan => "plan",
+            Reason::Admin => "admin",
+        }
+    }
+}
+
+impl PrefetchConfig {
+    pub fn watches(&self, repo: &str, name: &str) -> bool {
+        self.watch.iter().any(|pattern| match pattern.split_once('/') {
+            Some((repo_pattern, name_pattern)) => glob::matches(repo_pattern, repo) && glob::matches(name_pattern, name),
+            None => glob::matches(pattern, name),
+        })
+    }
+}
+
+impl Prefetcher {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            progress: Mutex::default(),
+            ready: Condvar::new(),
+            next_read: Mutex::new(Instant::now()),
+            config: config.prefetch.clone(),
+        }
+    }
+    /// What to fetch after a refresh of `repo` from `previous` to `state`.
+    pub fn jobs(&self, repo: &Arc<str>, previous: &HashMap<Arc<str>, Package>, state: &State, diff: &Diff) -> Vec<Job> {
+        let mut jobs = Vec::new();
+        if self.config.updates {
+            for upgraded in diff.upgraded.iter() {
+                let was_cached = previous.get(upgraded.name.as_ref()).is_some_and(|pkg| !matches!(pkg.cache.get(), DataSource::Empty));
+                if let Some(package) = state.packages.get(upgraded.name.as_ref()).filter(|_| was_cached) {
+                    jobs.push(Job { repo: repo.clone(), package: package.clone(), reason: Reason::Update });
+                }
+            }
+        }
+        if !self.config.watch.is_empty() {
+            let mut watched = state.packages.values()
+                .filter(|pkg| self.config.watches(repo, &pkg.desc.name))
+                .filter(|pkg| matches!(pkg.cache.get(), DataSource::Empty))
+                .filter(|pkg| !jobs.iter().any(|job| job.package.desc.filename == pkg.desc.filename))
+                .map(|pkg| Job { repo: repo.clone(), package: pkg.clone(), reason: Reason::Watch })
+                .collect::<Vec<_>>();
+            watched.sort_by(|a, b| a.package.desc.name.cmp(&b.package.desc.name));
+            jobs.extend(watched);
+        }
+        jobs
+    }
+    /// Queues `jobs`, leaving out files already queued or being fetched.
+    pub fn push(&self, jobs: impl IntoIterator<Item = Job>) {
+        let mut progress = self.progress.lock().unwrap();
+        for job in jobs {
+            let filename = &job.package.desc.filename;
+            if progress.queued.iter().any(|v| &v.package.desc.filename == filename) || progress.active.iter().any(|v| &v.1 == filename) {
+                continue;
+            }
+            progress.queued.push_back(job);
+        }
+        self.ready.notify_all();
+    }
+    /// Waits for the next job and marks it as active.
+    pub fn next(&self) -> Job {
+        let mut progress = self.progress.lock().unwrap();
+        loop {
+            if let Some(job) = progress.queued.pop_front() {
+                progress.active.push((job.repo.clone(), job.package.desc.filename.clone(), job.reason));
+                return job;
+            }
+            progress = self.ready.wait(progress).unwrap();
+        }
+    }
+    /// Called by every prefetch download after reading `len` bytes, sleeps until
+    /// the downloads together are back within `max_rate`.
+    pub fn throttle(&self, len: usize) {
+        if self.config.max_rate == 0 {
+            return;
+        }
+        let until = {
+            let mut next_read = self.next_read.lock().unwrap();
+            let read = (*next_read).max(Instant::now());
+            *next_read = read + Duration::from_secs_f64(len as f64 / self.config.max_rate as f64);
+            *next_read
+        };
+        std::thread::sleep(until.saturating_duration_since(Instant::now()));
+    }
+    pub fn finish(&self, job: &Job, outcome: Outcome) {
+        let mut progress = self.progress.lock().unwrap();
+        let filename = &job.package.desc.filename;
+        progress.active.retain(|v| &v.1 != filename);
+        match outcome {
+            Outcome::Done => {
+                progress.done += 1;
+                progress.bytes += job.package.desc.csize as u64;
+            }
+            Outcome::Cached | Outcome::Removed => (),
+            Outcome::Failed(_) => progress.failed += 1,
+        }
+        progress.finished.push_front(Finished {
+            repo: job.repo.clone(),
+            filename: filename.clone(),
+            reason: job.reason,
+            size: job.package.desc.csize,
+            outcome,
+            time: DateTime::now(),
+        });
+        progress.finished.truncate(HISTORY);
+    }
+    pub fn progress(&self) -> std::sync::MutexGuard<'_, Progress> {
+        self.progress.lock().unwrap()
+    }
+}
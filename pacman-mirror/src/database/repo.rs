@@ -1,31 +1,69 @@
-use std::sync::{atomic::AtomicBool, Arc, RwLock};
-use crate::{database::mirror_data::MirrorData, Config};
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+use crate::{database::{local::{LocalIndex, LocalRepo}, mirror_data::MirrorData, prefetch::Prefetcher, search::SearchIndex, snapshot::Snapshots}, metrics::RepoMetrics, Config};
 
 pub use state::State;
 
+pub mod changelog;
+pub mod diff;
 pub mod state;
 mod refresh;
-mod get_all;
+mod local;
+mod persist;
 
 
 pub struct Repo {
     pub name: Arc<str>,
     pub config: Arc<Config>,
-    pub mirrors: Vec<MirrorData>,
+    pub mirrors: Vec<Arc<MirrorData>>,
+    /// Local repos whose packages replace the ones from `mirrors`, whatever their version.
+    pub overlays: Vec<Arc<MirrorData>>,
     pub state: RwLock<State>,
-    is_updating: AtomicBool,
+    pub search: RwLock<Arc<SearchIndex>>,
+    pub snapshots: Arc<Snapshots>,
+    pub prefetch: Arc<Prefetcher>,
+    /// Oldest first.
+    changelog: RwLock<Vec<Arc<changelog::Entry>>>,
+    pub metrics: RepoMetrics,
+    /// Set while a refresh or upload is changing `state`, with `updated` notified once it's done.
+    updating: Mutex<bool>,
+    updated: Condvar,
 }
 
 impl Repo {
-    pub fn empty(config: Arc<Config>, name: Arc<str>) -> Repo {
+    pub fn local_config(&self) -> Option<&LocalRepo> {
+        self.config.local.iter().find(|v| v.name == self.name)
+    }
+    pub fn local_index(&self) -> Option<&LocalIndex> {
+        self.mirrors.iter()
+            .filter(|mirror| mirror.repo_name == self.name)
+            .find_map(|mirror| mirror.local.as_deref())
+    }
+    pub fn is_overlay(&self, mirror: &MirrorData) -> bool {
+        mirror.repo_name != self.name
+    }
+    pub fn empty(config: Arc<Config>, snapshots: Arc<Snapshots>, prefetch: Arc<Prefetcher>, name: Arc<str>, overlays: Vec<Arc<MirrorData>>) -> Repo {
         let mirrors = Vec::from_iter(config.mirrors.iter()
-            .map(|mirror| MirrorData::new(&config, mirror, name.clone())));
+            .map(|mirror| Arc::new(MirrorData::new(&config, mirror, name.clone()))));
+        Self::with_mirrors(config, snapshots, prefetch, name, mirrors, overlays)
+    }
+    pub fn local(config: Arc<Config>, snapshots: Arc<Snapshots>, prefetch: Arc<Prefetcher>, mirror: Arc<MirrorData>) -> Repo {
+        let name = mirror.repo_name.clone();
+        Self::with_mirrors(config, snapshots, prefetch, name, vec![mirror], Vec::new())
+    }
+    fn with_mirrors(config: Arc<Config>, snapshots: Arc<Snapshots>, prefetch: Arc<Prefetcher>, name: Arc<str>, mirrors: Vec<Arc<MirrorData>>, overlays: Vec<Arc<MirrorData>>) -> Repo {
         Self {
             name,
             config,
             mirrors,
+            overlays,
             state: RwLock::new(State::default()),
-            is_updating: AtomicBool::new(false),
+            search: RwLock::default(),
+            snapshots,
+            prefetch,
+            changelog: RwLock::default(),
+            metrics: RepoMetrics::default(),
+            updating: Mutex::new(false),
+            updated: Condvar::new(),
         }
     }
 }
@@ -0,0 +1,60 @@
+use std::{io::ErrorKind, path::Path, sync::Arc, time::SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{database::{repo::{diff::Diff, state::FetchType}, Repo}, date::DateTime};
+
+
+/// What one refresh changed.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Entry {
+    pub time: u64,
+    pub ty: FetchType,
+    #[serde(flatten)]
+    pub diff: Diff,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Changelog {
+    #[serde(default)]
+    entries: Vec<Arc<Entry>>,
+}
+
+impl Entry {
+    pub fn created(&self) -> DateTime {
+        DateTime::from_secs(self.time as i64)
+    }
+}
+
+impl Repo {
+    pub(super) fn record_change(&self, ty: FetchType, diff: Diff) {
+        let now = SystemTime::now();
+        let time = now.duration_since(SystemTime::UNIX_EPOCH).map(|v| v.as_secs()).unwrap_or(0);
+        let cutoff = now.checked_sub(self.config.changelog_retention)
+            .and_then(|v| v.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .map(|v| v.as_secs())
+            .unwrap_or(0);
+        let mut changelog = self.changelog.write().unwrap();
+        changelog.retain(|v| v.time >= cutoff);
+        changelog.push(Arc::new(Entry { time, ty, diff }));
+    }
+    /// Changelog entries, newest first.
+    pub fn changes(&self) -> Vec<Arc<Entry>> {
+        self.changelog.read().unwrap().iter().rev().cloned().collect()
+    }
+    pub(super) fn save_changelog(&self, dir: &Path) -> anyhow::Result<()> {
+        let data = toml::to_string(&Changelog { entries: self.changelog.read().unwrap().clone() })?;
+        std::fs::write(dir.join("changelog.part"), data)?;
+        std::fs::rename(dir.join("changelog.part"), dir.join("changelog.toml"))?;
+        Ok(())
+    }
+    pub(super) fn load_changelog(&self, dir: &Path) -> anyhow::Result<()> {
+        let changelog: Changelog = match std::fs::read_to_string(dir.join("changelog.toml")) {
+            Ok(data) => toml::from_str(&data)?,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+        *self.changelog.write().unwrap() = changelog.entries;
+        Ok(())
+    }
+}
@@ -0,0 +1,133 @@
+use std::{cmp::Ordering, collections::HashMap, fmt::Display, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+
+use crate::database::package::Package;
+
+/// How many packages of each kind are listed when a diff is printed.
+const SHOWN: usize = 50;
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Version {
+    pub name: Arc<str>,
+    pub version: Arc<str>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Change {
+    pub name: Arc<str>,
+    pub old: Arc<str>,
+    pub new: Arc<str>,
+}
+
+/// What changed between two sets of served packages, sorted by name.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Diff {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub added: Vec<Version>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub removed: Vec<Version>,
+    /// Also has packages rebuilt under the same version.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub upgraded: Vec<Change>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub downgraded: Vec<Change>,
+}
+
+impl Version {
+    fn new(pkg: &Package) -> Self {
+        Self { name: pkg.desc.name.clone(), version: pkg.desc.version.clone() }
+    }
+}
+
+impl Diff {
+    pub fn new(old: &HashMap<Arc<str>, Package>, new: &HashMap<Arc<str>, Package>) -> Self {
+        let mut diff = Self::default();
+        for (name, pkg) in new.iter() {
+            let Some(old) = old.get(name) else {
+                diff.added.push(Version::new(pkg));
+                continue;
+            };
+            if old.desc.filename == pkg.desc.filename {
+                continue;
+            }
+            let change = Change { name: name.clone(), old: old.desc.version.clone(), new: pkg.desc.version.clone() };
+            match vercmp::alpm_pkg_ver_cmp(&change.new, &change.old) {
+                Ordering::Less => diff.downgraded.push(change),
+                _ => diff.upgraded.push(change),
+            }
+        }
+        diff.removed.extend(old.iter()
+            .filter(|(name, _)| !new.contains_key(*name))
+            .map(|(_, pkg)| Version::new(pkg)));
+        diff.added.sort_by(|a, b| a.name.cmp(&b.name));
+        diff.removed.sort_by(|a, b| a.name.cmp(&b.name));
+        diff.upgraded.sort_by(|a, b| a.name.cmp(&b.name));
+        diff.downgraded.sort_by(|a, b| a.name.cmp(&b.name));
+        diff
+    }
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.upgraded.is_empty() && self.downgraded.is_empty()
+    }
+    /// Counts of each kind, e.g. `3 upgraded, 1 removed`, skipping the empty ones.
+    pub fn summary(&self) -> String {
+        [("added", self.added.len()), ("removed", self.removed.len()), ("upgraded", self.upgraded.len()), ("downgraded", self.downgraded.len())]
+            .into_iter()
+            .filter(|(_, len)| *len > 0)
+            .map(|(kind, len)| format!("{len} {kind}"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+impl Display for Diff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let more = |f: &mut std::fmt::Formatter<'_>, len: usize| match len > SHOWN {
+            true => write!(f, "\n  ... and {} more", len - SHOWN),
+            false => Ok(()),
+        };
+        write!(f, "{}", self.summary())?;
+        for (sign, list) in [('+', &self.added), ('-', &self.removed)] {
+            for v in list.iter().take(SHOWN) {
+                write!(f, "\n  {sign} {} {}", v.name, v.version)?;
+            }
+            more(f, list.len())?;
+        }
+        for list in [&self.upgraded, &self.downgraded] {
+            for v in list.iter().take(SHOWN) {
+                write!(f, "\n  ~ {} {} -> {}", v.name, v.old, v.new)?;
+            }
+            more(f, list.len())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::desc::Desc;
+
+    use super::*;
+
+    fn packages(versions: &[(&str, &str)]) -> HashMap<Arc<str>, Package> {
+        versions.iter()
+            .map(|(name, version)| (Arc::from(*name), Package::new(Desc::test(name, version, &[]))))
+            .collect()
+    }
+
+    #[test]
+    fn new() {
+        let old = packages(&[("bash", "5.2-1"), ("gone", "1-1"), ("linux", "6.10-1"), ("vim", "1:9.1-1"), ("zlib", "1.3-1")]);
+        let new = packages(&[("bash", "5.2-1"), ("added", "1-1"), ("linux", "6.11-1"), ("vim", "9.2-1"), ("zlib", "1.3-1")]);
+        let diff = Diff::new(&old, &new);
+        let names = |list: &[Version]| list.iter().map(|v| v.name.to_string()).collect::<Vec<_>>();
+        assert_eq!(names(&diff.added), ["added"]);
+        assert_eq!(names(&diff.removed), ["gone"]);
+        assert_eq!(diff.upgraded.iter().map(|v| (v.name.as_ref(), v.old.as_ref(), v.new.as_ref())).collect::<Vec<_>>(), [("linux", "6.10-1", "6.11-1")]);
+        // losing the epoch is a downgrade
+        assert_eq!(diff.downgraded.iter().map(|v| v.name.as_ref()).collect::<Vec<_>>(), ["vim"]);
+        assert_eq!(diff.summary(), "1 added, 1 removed, 1 upgraded, 1 downgraded");
+        assert!(Diff::new(&new, &new).is_empty());
+    }
+}
@@ -0,0 +1,66 @@
+use std::{io::Read, sync::Arc, time::Instant};
+
+use log::error;
+use replay_buffer::ReplayBufferWriter;
+
+use crate::database::{desc::Desc, local::AddError, repo::diff::Diff, Repo};
+
+
+impl Repo {
+    /// Serves `packages` as the whole contents of this local repo.
+    fn serve_local(&self, packages: Vec<Arc<Desc>>) {
+        let start = Instant::now();
+        let Some(mirror) = self.mirrors.iter().find(|mirror| !self.is_overlay(mirror)) else {
+            return;
+        };
+        let writer = ReplayBufferWriter::new();
+        writer.extend(packages);
+        mirror.restore(writer.source().clone());
+        drop(writer);
+        self.rebuild(start);
+    }
+    /// Builds the packages to serve again from the lists mirrors already have.
+    fn rebuild(&self, start: Instant) {
+        let (new_state, diff) = {
+            let state = self.state.read().unwrap();
+            let mut new_state = self.build(&state.packages, &self.lists(), state.ty, state.last_updated);
+            // the mirrors are as they were, and so is how their last refresh went
+            new_state.degraded = state.degraded.clone();
+            new_state.failed = state.failed;
+            let diff = Diff::new(&state.packages, &new_state.packages);
+            (new_state, diff)
+        };
+        self.publish(new_state, diff, Vec::new(), start);
+    }
+    /// Picks up changes to the local repos overlaying this one without fetching from its mirrors.
+    pub fn refresh_overlays(&self) {
+        let _guard = self.lock_update();
+        let start = Instant::now();
+        let ty = self.state.read().unwrap().ty;
+        for mirror in self.overlays.iter() {
+            let mut writer = ReplayBufferWriter::new();
+            match mirror.update(&mut writer, ty) {
+                Ok(()) => mirror.restore(writer.source().clone()),
+                Err(err) => error!("mirror {}: {err:?}", mirror.repo_url),
+            }
+        }
+        self.rebuild(start);
+    }
+    /// Adds a package to this local repo. It's served before any older
+    /// versions are deleted, so every package listed can still be downloaded.
+    pub fn upload(&self, src: impl Read, sig: Option<&[u8]>) -> Result<Option<Arc<Desc>>, AddError> {
+        let (Some(local), Some(index)) = (self.local_config(), self.local_index()) else {
+            return Ok(None);
+        };
+        let _guard = self.lock_update();
+        index.add(src, sig, &self.config.arch, local.keyring.as_deref(), |packages| self.serve_local(packages)).map(Some)
+    }
+    /// Removes every version of a package from this local repo, returning how many there were.
+    pub fn delete(&self, name: &str) -> anyhow::Result<usize> {
+        let Some(index) = self.local_index() else {
+            return Ok(0);
+        };
+        let _guard = self.lock_update();
+        index.remove(name, |packages| self.serve_local(packages))
+    }
+}
@@ -0,0 +1,164 @@
+use std::{collections::BTreeMap, fs::File, io::{BufReader, BufWriter, ErrorKind}, path::{Path, PathBuf}, sync::Arc, time::{Duration, SystemTime}};
+
+use anyhow::Context;
+use log::info;
+use replay_buffer::ReplayBufferWriter;
+use serde::{Deserialize, Serialize};
+use sha2::Digest;
+
+use crate::database::{archive, desc::Desc, package::Package, repo::state::FetchType, search::SearchIndex, Repo};
+
+
+/// Written last, so a state directory without it is never half read.
+#[derive(Serialize, Deserialize)]
+struct Meta {
+    last_updated: u64,
+    ty: FetchType,
+    /// List file of each mirror, by repo url.
+    mirrors: BTreeMap<Arc<str>, String>,
+}
+
+fn write_list(path: &Path, ty: FetchType, descs: impl IntoIterator<Item = Arc<Desc>>) -> anyhow::Result<()> {
+    let part = path.with_extension("part");
+    let mut writer = archive::Writer::new(BufWriter::new(File::create(&part)?), ty, 1);
+    for desc in descs {
+        writer.append(&desc)?;
+    }
+    writer.finish()?.into_inner()?.sync_all()?;
+    std::fs::rename(&part, path)?;
+    Ok(())
+}
+
+fn read_list(path: &Path, ty: FetchType, dst: impl FnMut(Arc<Desc>)) -> anyhow::Result<()> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    archive::read(BufReader::new(file), ty, dst)
+}
+
+impl Repo {
+    fn state_dir(&self) -> Option<PathBuf> {
+        self.config.state_dir.as_ref().map(|v| v.join(self.name.as_ref()))
+    }
+    /// Writes the served packages and every mirror's package list to `state_dir`.
+    pub fn save_state(&self) -> anyhow::Result<()> {
+        let Some(dir) = self.state_dir() else {
+            return Ok(());
+        };
+        std::fs::create_dir_all(&dir)?;
+        let (ty, last_updated, packages) = {
+            let state = self.state.read().unwrap();
+            (state.ty, state.last_updated, Vec::from_iter(state.packages.values().map(|v| v.desc.clone())))
+        };
+        let last_updated = last_updated.duration_since(SystemTime::UNIX_EPOCH).map(|v| v.as_secs()).unwrap_or(0);
+        write_list(&dir.join("packages.tar.gz"), ty, packages)?;
+
+        let mut mirrors = BTreeMap::new();
+        for mirror in self.mirrors.iter().chain(self.overlays.iter()) {
+            let name = format!("{}.tar.gz", hex::encode(&sha2::Sha256::digest(mirror.repo_url.as_bytes())[..8]));
+            let packages = mirror.state.read().unwrap().packages.read();
+            write_list(&dir.join(&name), ty, packages)?;
+            mirrors.insert(mirror.repo_url.clone(), name);
+        }
+        self.save_changelog(&dir)?;
+        let meta = toml::to_string(&Meta { last_updated, ty, mirrors })?;
+        std::fs::write(dir.join("state.part"), meta)?;
+        std::fs::rename(dir.join("state.part"), dir.join("state.toml"))?;
+        Ok(())
+    }
+    /// Restores what [`Repo::save_state`] wrote, if anything.
+    pub fn load_state(&self) -> anyhow::Result<()> {
+        let Some(dir) = self.state_dir() else {
+            return Ok(());
+        };
+        let meta: Meta = match std::fs::read_to_string(dir.join("state.toml")) {
+            Ok(data) => toml::from_str(&data)?,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+        self.load_changelog(&dir)?;
+        for mirror in self.mirrors.iter().chain(self.overlays.iter()) {
+            let Some(name) = meta.mirrors.get(&mirror.repo_url) else {
+                continue;
+            };
+            let writer = ReplayBufferWriter::new();
+            read_list(&dir.join(name), meta.ty, |desc| writer.push(desc))?;
+            mirror.restore(writer.source().clone());
+        }
+        // held packages are only known from what was served before
+        let mut packages = Vec::new();
+        read_list(&dir.join("packages.tar.gz"), meta.ty, |desc| packages.push(desc))?;
+        let packages = packages.into_iter()
+            .map(|desc| (desc.name.clone(), Package::new(desc)))
+            .collect();
+
+        let state = self.build(&packages, &self.lists(), meta.ty, SystemTime::UNIX_EPOCH + Duration::from_secs(meta.last_updated));
+        *self.search.write().unwrap() = Arc::new(SearchIndex::new(state.packages.values()));
+        *self.state.write().unwrap() = state;
+        info!("Loaded {} ({:?}): {} packages", self.name, meta.ty, self.state.read().unwrap().packages.len());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{config::Config, database::{mirror::Mirror, prefetch::Prefetcher, repo::state::State, snapshot::Snapshots}};
+
+    use super::*;
+
+    fn repo(dir: &Path) -> Repo {
+        let config = Arc::new(Config {
+            mirrors: vec![Mirror::new("https://a/$repo".into()), Mirror::new("https://b/$repo".into())],
+            state_dir: Some(dir.into()),
+            ..Config::default()
+        });
+        Repo::empty(config.clone(), Arc::new(Snapshots::new(&config)), Arc::new(Prefetcher::new(&config)), "core".into(), Vec::new())
+    }
+
+    fn filenames(packages: impl IntoIterator<Item = Arc<Desc>>) -> Vec<Arc<str>> {
+        let mut filenames = Vec::from_iter(packages.into_iter().map(|v| v.filename.clone()));
+        filenames.sort();
+        filenames
+    }
+
+    /// Files lists are only kept by a `FetchType::Files` state.
+    fn with_files(desc: Arc<Desc>) -> Arc<Desc> {
+        let mut desc = Desc::clone(&desc);
+        desc.files = Some(format!("%FILES%\nusr/bin/{}\n", desc.name).into());
+        Arc::new(desc)
+    }
+
+    #[test]
+    fn saves_and_loads_the_state() {
+        let dir = std::env::temp_dir().join(format!("pacman-mirror-state-{}", std::process::id()));
+        let saved = repo(&dir);
+        let lists = [
+            vec![Desc::test("foo", "2-1", &[]), Desc::test("bar", "1-1", &[("DEPENDS", "foo")])],
+            vec![Desc::test("foo", "1-1", &[])],
+        ];
+        for (mirror, descs) in saved.mirrors.iter().zip(&lists) {
+            let writer = ReplayBufferWriter::new();
+            writer.extend(descs.iter().cloned().map(with_files));
+            mirror.restore(writer.source().clone());
+        }
+        let last_updated = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        *saved.state.write().unwrap() = saved.build(&HashMap::new(), &saved.lists(), FetchType::Files, last_updated);
+        saved.save_state().unwrap();
+
+        let loaded = repo(&dir);
+        loaded.load_state().unwrap();
+        for (a, b) in saved.mirrors.iter().zip(loaded.mirrors.iter()) {
+            let (a, b) = (a.state.read().unwrap().packages.read(), b.state.read().unwrap().packages.read());
+            assert_eq!(filenames(a), filenames(b));
+        }
+        let (a, b) = (saved.state.read().unwrap(), loaded.state.read().unwrap());
+        assert_eq!(b.ty, FetchType::Files);
+        assert_eq!(b.last_updated, last_updated);
+        assert_eq!(filenames(a.packages.values().map(|v| v.desc.clone())), filenames(b.packages.values().map(|v| v.desc.clone())));
+        assert_eq!(b.packages["bar"].desc.get("DEPENDS"), Some("foo"));
+        assert_eq!(b.packages["bar"].desc.files.as_deref(), Some("%FILES%\nusr/bin/bar\n"));
+        let urls = |state: &State| Vec::from_iter(state.packages["foo"].mirrors.iter().map(|v| v.repo_url.clone()));
+        assert_eq!(urls(&b), urls(&a));
+        _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -1,80 +1,339 @@
-use std::{sync::{atomic::{self, AtomicBool}, mpsc}, time::SystemTime};
+use std::{cmp::Ordering, collections::HashMap, sync::Arc, time::{Instant, SystemTime}};
 
 use iter_iterator::IterIterator;
 use itertools::Itertools;
-use log::{debug, error, info};
-use rayon::iter::{IntoParallelRefMutIterator, ParallelIterator};
+use log::{debug, error, info, warn};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use replay_buffer::{ReplayBuffer, ReplayBufferWriter};
 
-use crate::database::{package::Package, repo::state::FetchType, Repo};
+use crate::{cache::DataSource, database::{desc::Desc, mirror_data::MirrorData, package::Package, prefetch::Job, repo::{diff::Diff, state::{Degraded, FetchType}, State}, rules::{self, Filtered, Verdict}, search::SearchIndex, Repo}};
 
 
-struct AtomicStoreGuard<'a>(&'a AtomicBool);
+/// Held while changing `state`, see [`Repo::lock_update`].
+pub(super) struct UpdateGuard<'a>(&'a Repo);
 
-impl<'a> Drop for AtomicStoreGuard<'a> {
+/// The complete package list of one mirror.
+pub(super) struct MirrorList {
+    pub mirror: Arc<MirrorData>,
+    pub packages: Arc<ReplayBuffer<Arc<Desc>>>,
+}
+
+impl<'a> Drop for UpdateGuard<'a> {
     fn drop(&mut self) {
-        self.0.store(false, atomic::Ordering::Relaxed);
+        *self.0.updating.lock().unwrap() = false;
+        self.0.updated.notify_all();
+    }
+}
+
+/// Fetches a new list from `mirror` without serving any of it, or keeps its
+/// last one if that fails.
+fn fetch(mirror: &Arc<MirrorData>, ty: FetchType) -> (MirrorList, bool) {
+    let previous = MirrorList { mirror: mirror.clone(), packages: mirror.state.read().unwrap().packages.clone() };
+    if mirror.is_quarantined() {
+        debug!("Skipping quarantined mirror {}", mirror.repo_url);
+        return (MirrorList { mirror: mirror.clone(), packages: ReplayBufferWriter::new().source().clone() }, false);
+    }
+    let mut writer = ReplayBufferWriter::new();
+    match mirror.update(&mut writer, ty) {
+        Ok(()) => (MirrorList { mirror: mirror.clone(), packages: writer.source().clone() }, true),
+        Err(err) => {
+            error!("mirror {}: {err:?}", mirror.repo_url);
+            (previous, false)
+        }
     }
 }
 
 impl Repo {
+    /// What every mirror served last.
+    pub(super) fn lists(&self) -> Vec<MirrorList> {
+        self.mirrors.iter()
+            .chain(self.overlays.iter())
+            .map(|mirror| MirrorList { mirror: mirror.clone(), packages: mirror.state.read().unwrap().packages.clone() })
+            .collect()
+    }
     pub fn should_refresh(&self, ty: FetchType) -> bool {
-        self.is_updating.load(atomic::Ordering::Relaxed) || self.state.read().unwrap().should_refresh(&self.config, ty)
+        *self.updating.lock().unwrap() || self.state.read().unwrap().should_refresh(&self.config, ty)
+    }
+    /// Waits for any refresh or upload already running to finish first.
+    pub(super) fn lock_update(&self) -> UpdateGuard<'_> {
+        let mut updating = self.updated.wait_while(self.updating.lock().unwrap(), |v| *v).unwrap();
+        *updating = true;
+        UpdateGuard(self)
     }
-    pub fn try_refresh(&self, signal: Option<mpsc::Sender<()>>, ty: FetchType) {
-        if self.is_updating.swap(true, atomic::Ordering::Relaxed) {
-            return;
+    /// Returns once no refresh or upload is running.
+    pub fn wait_for_update(&self) {
+        drop(self.updated.wait_while(self.updating.lock().unwrap(), |v| *v).unwrap());
+    }
+    pub fn try_refresh(&self, ty: FetchType) {
+        {
+            let mut updating = self.updating.lock().unwrap();
+            if *updating {
+                return;
+            }
+            *updating = true;
         }
-
-        let _guard = AtomicStoreGuard(&self.is_updating);
+        self.refresh(UpdateGuard(self), ty);
+    }
+    /// Refreshes straight away, waiting for any refresh already running to finish first.
+    pub fn refresh_now(&self, ty: FetchType) {
+        self.refresh(self.lock_update(), ty);
+    }
+    fn refresh(&self, _guard: UpdateGuard, ty: FetchType) {
         let repo_name = &self.name;
+        let start = Instant::now();
         debug!("Refreshing {repo_name} ({ty:?})");
 
-        let mut buf_writers = self.mirrors.iter()
-            .map(|v| (v, v.prepare_for_update()))
-            .collect_vec();
-        drop(signal);
+        let fetched = self.mirrors.iter()
+            .chain(self.overlays.iter())
+            .collect_vec()
+            .into_par_iter()
+            .map(|mirror| fetch(mirror, ty))
+            .collect::<Vec<_>>();
+        let upstream_ok = fetched[..self.mirrors.len()].iter().any(|(_, ok)| *ok);
+        let lists = fetched.into_iter().map(|(list, _)| list).collect_vec();
 
-        buf_writers.par_iter_mut().for_each(|(mirror, writer)| {
-            if let Err(err) = mirror.update(writer, ty) {
-                error!("mirror {}: {err:?}", mirror.repo_url);
-            }
+        let checked = upstream_ok.then(|| {
+            let state = self.state.read().unwrap();
+            let new_state = self.build(&state.packages, &lists, ty, SystemTime::now());
+            let diff = Diff::new(&state.packages, &new_state.packages);
+            let rejected = self.check_thresholds(state.packages.len(), &new_state, &diff);
+            let jobs = self.prefetch.jobs(&self.name, &state.packages, &new_state, &diff);
+            (new_state, diff, jobs, rejected)
         });
+        let (new_state, diff, jobs) = match checked {
+            Some((new_state, diff, jobs, None)) => (new_state, diff, jobs),
+            rejected => {
+                let degraded = match rejected {
+                    Some((_, diff, _, Some(degraded))) => {
+                        warn!("Rejected refresh of {repo_name}: {diff}");
+                        degraded
+                    }
+                    _ => Degraded::Offline,
+                };
+                warn!("Keeping the last good state of {repo_name}: {degraded}");
+                let mut state = self.state.write().unwrap();
+                state.degraded = Some(degraded);
+                // try again after the usual timeout rather than on every request
+                state.failed = Some((ty, SystemTime::now()));
+                self.metrics.refresh_duration.observe(start.elapsed());
+                return;
+            }
+        };
+        // mirrors only serve their new lists once the state built from them is
+        for list in lists {
+            list.mirror.restore(list.packages);
+        }
+        self.publish(new_state, diff, jobs, start);
+    }
+    /// Serves `new_state` in place of the current one, which `diff` was taken from.
+    pub(super) fn publish(&self, new_state: State, diff: Diff, jobs: Vec<Job>, start: Instant) {
+        let repo_name = &self.name;
+        let (ty, count) = (new_state.ty, new_state.packages.len());
+        let search = Arc::new(SearchIndex::new(new_state.packages.values()));
+        let previous = std::mem::replace(&mut *self.state.write().unwrap(), new_state);
+        *self.search.write().unwrap() = search;
+        // the first list isn't a change, the whole repo would be listed as added
+        if previous.last_updated == SystemTime::UNIX_EPOCH || previous.packages.is_empty() {
+            info!("Refreshed {repo_name} ({ty:?}): {count} packages");
+        } else if diff.is_empty() {
+            info!("Refreshed {repo_name} ({ty:?}): no changes");
+        } else {
+            info!("Refreshed {repo_name} ({ty:?}): {}", diff.summary());
+            self.record_change(ty, diff);
+        }
+        if !jobs.is_empty() {
+            info!("Prefetching {} packages from {repo_name}", jobs.len());
+            self.prefetch.push(jobs);
+        }
+        self.metrics.refresh_duration.observe(start.elapsed());
 
-        let iter = IterIterator::new(buf_writers.into_iter()
-            .map(|(mirror, writer)| (writer.source().clone().read(), mirror))
+        if let Err(err) = self.save_state() {
+            error!("Failed to save state of {repo_name}: {err:?}");
+        }
+        if let Err(err) = self.snapshots.record(self) {
+            error!("Failed to save snapshot of {repo_name}: {err:?}");
+        }
+    }
+    /// Local repos only change through uploads, so they're trusted whatever the result.
+    fn check_thresholds(&self, previous: usize, state: &State, diff: &Diff) -> Option<Degraded> {
+        match self.local_index() {
+            Some(_) => None,
+            None => self.config.thresholds.check(previous, state.packages.len(), diff.removed.len()),
+        }
+    }
+    /// Builds the packages to serve from `lists`, starting from the ones in `previous`.
+    pub(super) fn build(&self, previous: &HashMap<Arc<str>, Package>, lists: &[MirrorList], ty: FetchType, last_updated: SystemTime) -> State {
+        let repo_name = &self.name;
+        let mut overlay = HashMap::<Arc<str>, (Arc<Desc>, Arc<MirrorData>)>::new();
+        for list in lists.iter().filter(|v| self.is_overlay(&v.mirror)) {
+            for desc in list.packages.read() {
+                overlay.entry(desc.name.clone()).or_insert_with(|| (desc, list.mirror.clone()));
+            }
+        }
+        // a quarantined mirror may still have a list from before, but it's no source of anything
+        let iter = IterIterator::new(lists.iter()
+            .filter(|v| !self.is_overlay(&v.mirror) && !v.mirror.is_quarantined())
+            .map(|v| (v.packages.read(), v.mirror.clone()))
             .collect());
 
-        let mut state = self.state.write().unwrap();
-        state.last_updated = SystemTime::now();
-        state.ty = ty;
-
-        let mut added = 0;
-        let mut removed = 0;
+        let rules = &self.config.rules;
+        let mut packages = previous.clone();
+        let mut filtered = HashMap::new();
 
-        for pkg in state.packages.values_mut() {
+        // packages that have left an overlay go back to their upstream version
+        packages.retain(|name, pkg| overlay.contains_key(name) || !pkg.mirrors.iter().any(|v| self.is_overlay(v)));
+        for pkg in packages.values_mut() {
             pkg.mirrors.clear();
         }
         for (desc, mirror) in iter {
-            let pkg = state.packages.entry(desc.name.clone()).or_insert_with(|| {
-                added += 1;
-                Package::new(desc.clone())
-            });
-            if vercmp::alpm_pkg_ver_cmp(&desc.version, &pkg.desc.version) == std::cmp::Ordering::Greater {
+            if overlay.contains_key(&desc.name) {
+                continue;
+            }
+            // versions kept out by a rule never get in, whichever mirror lists them first,
+            // and a held package is only fetched from the mirrors still listing its file
+            if let Some((rule, verdict)) = rules::check(rules, repo_name, &desc) {
+                if filtered.get(&desc.name).is_none_or(|v: &Filtered| vercmp::alpm_pkg_ver_cmp(&desc.version, &v.version) == Ordering::Greater) {
+                    filtered.insert(desc.name.clone(), Filtered { rule, verdict, version: desc.version.clone() });
+                }
+                continue;
+            }
+            let pkg = packages.entry(desc.name.clone()).or_insert_with(|| Package::new(desc.clone()));
+            if vercmp::alpm_pkg_ver_cmp(&desc.version, &pkg.desc.version) == Ordering::Greater {
                 *pkg = Package::new(desc.clone());
+            } else if pkg.desc.filename == desc.filename && pkg.desc.files.is_none() && desc.files.is_some() {
+                // same file, fetched again as part of a `.files` database
+                pkg.desc = desc.clone();
             }
-            pkg.mirrors.push(mirror.repo_url.clone());
+            pkg.mirrors.push(mirror);
         }
-        state.packages.retain(|_, pkg| {
-            let r = pkg.mirrors.len() > 0;
-            if !r { removed += 1 };
-            r
+        for (name, (desc, mirror)) in overlay {
+            let pkg = packages.entry(name).or_insert_with(|| Package::new(desc.clone()));
+            if pkg.desc.filename != desc.filename {
+                *pkg = Package::new(desc);
+            } else if pkg.desc.files.is_none() {
+                pkg.desc = desc;
+            }
+            pkg.mirrors.push(mirror);
+        }
+        packages.retain(|name, pkg| {
+            if pkg.mirrors.is_empty() {
+                // a held version no mirror has anymore can only be served from the cache
+                let held = filtered.get(name).is_some_and(|v| v.verdict == Verdict::Hold);
+                let cached = !matches!(pkg.cache.get(), DataSource::Empty);
+                if held && !cached {
+                    warn!("{repo_name}/{name} {} is held, but neither cached nor on any mirror", pkg.desc.version);
+                }
+                return held && cached;
+            }
+            if let Some((rule, verdict)) = rules::check(rules, repo_name, &pkg.desc) {
+                filtered.entry(name.clone()).or_insert(Filtered { rule, verdict, version: pkg.desc.version.clone() });
+                return false;
+            }
+            true
         });
 
-        state.packages_by_filename = state.packages.values()
+        let packages_by_filename = packages.values()
             .map(|pkg| (pkg.desc.filename.clone(), pkg.desc.name.clone()))
             .collect();
-
-        info!("Refreshed {repo_name} ({ty:?}): {added} added {removed} removed");
+        State { packages, packages_by_filename, filtered, last_updated, ty, degraded: None, failed: None }
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::{config::Config, database::{mirror::Mirror, prefetch::Prefetcher, snapshot::Snapshots}};
+
+    use super::*;
+
+    fn repo_with(rules: &str) -> Repo {
+        let config = Arc::new(Config {
+            mirrors: vec![Mirror::new("https://a/$repo".into()), Mirror::new("https://b/$repo".into())],
+            rules: toml::from_str::<toml::Table>(rules).unwrap()["rules"].clone().try_into().unwrap(),
+            ..Config::default()
+        });
+        Repo::empty(config.clone(), Arc::new(Snapshots::new(&config)), Arc::new(Prefetcher::new(&config)), "core".into(), Vec::new())
+    }
+
+    fn repo() -> Repo {
+        repo_with("rules = []")
+    }
+
+    fn list(mirror: &Arc<MirrorData>, descs: &[Arc<Desc>]) -> MirrorList {
+        let writer = ReplayBufferWriter::new();
+        writer.extend(descs.iter().cloned());
+        MirrorList { mirror: mirror.clone(), packages: writer.source().clone() }
+    }
+
+    #[test]
+    fn quarantined_mirrors_are_no_source() {
+        let repo = repo();
+        let (a, b) = (&repo.mirrors[0], &repo.mirrors[1]);
+        b.set_quarantined(true);
+        let lists = [
+            list(a, &[Desc::test("foo", "1-1", &[])]),
+            list(b, &[Desc::test("foo", "2-1", &[]), Desc::test("bar", "1-1", &[])]),
+        ];
+        let state = repo.build(&HashMap::new(), &lists, FetchType::Db, SystemTime::now());
+        assert_eq!(state.packages.len(), 1);
+        assert_eq!(state.packages["foo"].desc.version.as_ref(), "1-1");
+        assert!(state.packages["foo"].mirrors.iter().all(|v| Arc::ptr_eq(v, a)));
+    }
+
+    #[test]
+    fn holds_whatever_the_mirror_order() {
+        let repo = repo_with(r#"
+            [[rules]]
+            action = "hold"
+            package = "foo"
+            version = "<3"
+        "#);
+        let (a, b) = (&repo.mirrors[0], &repo.mirrors[1]);
+        for held_first in [true, false] {
+            let (held, allowed) = (Desc::test("foo", "3-1", &[]), Desc::test("foo", "2-1", &[]));
+            let (first, second) = if held_first { (held, allowed) } else { (allowed, held) };
+            let lists = [list(a, &[first]), list(b, &[second])];
+            let state = repo.build(&HashMap::new(), &lists, FetchType::Db, SystemTime::now());
+            assert_eq!(state.packages["foo"].desc.version.as_ref(), "2-1");
+            assert_eq!(state.filtered["foo"].version.as_ref(), "3-1");
+        }
+    }
+
+    #[test]
+    fn holds_the_previous_version() {
+        let repo = repo_with(r#"
+            [[rules]]
+            action = "hold"
+            package = "foo"
+            version = "<3"
+
+            [[rules]]
+            action = "exclude"
+            package = "bar"
+        "#);
+        let (a, b) = (&repo.mirrors[0], &repo.mirrors[1]);
+        let previous = HashMap::from([("foo".into(), Package::new(Desc::test("foo", "1-1", &[])))]);
+        let lists = [
+            list(a, &[Desc::test("foo", "3-1", &[]), Desc::test("bar", "1-1", &[])]),
+            list(b, &[Desc::test("foo", "1-1", &[])]),
+        ];
+        let state = repo.build(&previous, &lists, FetchType::Db, SystemTime::now());
+        assert_eq!(state.packages.len(), 1);
+        assert_eq!(state.packages["foo"].desc.version.as_ref(), "1-1");
+        // only the mirror that still has the held file serves it
+        assert!(state.packages["foo"].mirrors.iter().map(Arc::as_ptr).eq([Arc::as_ptr(b)]));
+        assert_eq!(state.filtered["foo"].version.as_ref(), "3-1");
+        assert_eq!(state.filtered["bar"].verdict, Verdict::Exclude);
+
+        // once no mirror has it, it's only kept if cached
+        let lists = [list(a, &[Desc::test("foo", "3-1", &[])]), list(b, &[Desc::test("foo", "4-1", &[])])];
+        let state = repo.build(&previous, &lists, FetchType::Db, SystemTime::now());
+        assert!(state.packages.is_empty());
+        assert_eq!(state.filtered["foo"].version.as_ref(), "4-1");
+        previous["foo"].cache.set(DataSource::File(Path::new("/cache/foo-1-1-x86_64.pkg.tar.zst").into()));
+        let state = repo.build(&previous, &lists, FetchType::Db, SystemTime::now());
+        assert_eq!(state.packages["foo"].desc.version.as_ref(), "1-1");
+        assert!(state.packages["foo"].mirrors.is_empty());
+    }
+}
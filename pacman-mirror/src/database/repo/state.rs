@@ -1,16 +1,63 @@
 use std::{collections::HashMap, sync::Arc, time::SystemTime};
 
-use crate::{database::package::Package, Config};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{database::{package::Package, rules::Filtered}, Config};
 
 
 pub struct State {
     pub packages: HashMap<Arc<str>, Package>,
     pub packages_by_filename: HashMap<Arc<str>, Arc<str>>,
+    pub filtered: HashMap<Arc<str>, Filtered>,
     pub last_updated: SystemTime,
     pub ty: FetchType,
+    /// Set when the last refresh was rejected, so `packages` are from an earlier one.
+    pub degraded: Option<Degraded>,
+    /// Type and time of the last refresh that failed or was rejected.
+    pub failed: Option<(FetchType, SystemTime)>,
+}
+
+#[derive(Debug, Clone, Error)]
+pub enum Degraded {
+    #[error("every mirror failed")]
+    Offline,
+    #[error("{removed} of {previous} packages would have been removed")]
+    TooManyRemoved { removed: usize, previous: usize },
+    #[error("only {count} packages would have been left, expected at least {min}")]
+    TooFew { count: usize, min: usize },
+}
+
+/// Limits on how much one refresh may change a repo before it's rejected.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Thresholds {
+    /// Percentage of the served packages a refresh may remove.
+    pub max_removed: f64,
+    pub min_packages: usize,
+}
+
+impl Thresholds {
+    /// Why a refresh from `previous` to `count` packages, removing `removed` of them, should be rejected.
+    pub fn check(&self, previous: usize, count: usize, removed: usize) -> Option<Degraded> {
+        if count < self.min_packages {
+            Some(Degraded::TooFew { count, min: self.min_packages })
+        } else if previous > 0 && removed as f64 * 100.0 > previous as f64 * self.max_removed {
+            Some(Degraded::TooManyRemoved { removed, previous })
+        } else {
+            None
+        }
+    }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+impl Default for Thresholds {
+    fn default() -> Self {
+        Self { max_removed: 50.0, min_packages: 0 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum FetchType {
     Db, Files,
 }
@@ -20,15 +67,41 @@ impl Default for State {
         Self {
             packages: HashMap::new(),
             packages_by_filename: HashMap::new(),
+            filtered: HashMap::new(),
             last_updated: SystemTime::UNIX_EPOCH,
             ty: FetchType::Db,
+            degraded: None,
+            failed: None,
         }
     }
 }
 
 impl State {
     pub fn should_refresh(&self, config: &Config, ty: FetchType) -> bool {
-        ty > self.ty || self.last_updated.elapsed().is_ok_and(|v| v > config.timeout)
+        let expired = |time: SystemTime| time.elapsed().is_ok_and(|v| v > config.timeout);
+        // a type that failed isn't tried again until the usual timeout has passed
+        if let Some((failed, at)) = self.failed
+            && failed >= ty && !expired(at) {
+            return false;
+        }
+        ty > self.ty || expired(self.last_updated)
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thresholds() {
+        let thresholds = Thresholds { max_removed: 50.0, min_packages: 10 };
+        assert!(thresholds.check(0, 100, 0).is_none());
+        assert!(thresholds.check(100, 50, 50).is_none());
+        assert!(matches!(thresholds.check(100, 49, 51), Some(Degraded::TooManyRemoved { removed: 51, previous: 100 })));
+        assert!(matches!(thresholds.check(100, 9, 0), Some(Degraded::TooFew { count: 9, min: 10 })));
+        // nothing was served before, so nothing can be removed
+        assert!(thresholds.check(0, 10, 0).is_none());
+        assert!(matches!(thresholds.check(0, 0, 0), Some(Degraded::TooFew { .. })));
+    }
+}
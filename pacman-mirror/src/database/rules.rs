@@ -0,0 +1,99 @@
+use std::{fmt::Display, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{database::{constraint::Constraint, desc::Desc}, glob};
+
+
+/// Limits which packages are served, for every repo or only the one named in `repo`.
+///
+/// ```toml
+/// [[rules]]
+/// action = "hold"
+/// package = "linux"
+/// version = "<=6.9.x"
+///
+/// [[rules]]
+/// action = "exclude"
+/// package = "nvidia-*"
+///
+/// [[rules]]
+/// repo = "custom"
+/// action = "allow"
+/// packages = ["foo", "bar-*"]
+/// ```
+#[derive(Debug,Serialize,Deserialize,Clone)]
+pub struct Rule {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repo: Option<Arc<str>>,
+    #[serde(flatten)]
+    pub action: Action,
+}
+
+#[derive(Debug,Serialize,Deserialize,Clone)]
+#[serde(tag = "action", rename_all = "lowercase")]
+pub enum Action {
+    /// Keeps serving the newest version matching `version`.
+    Hold { package: Box<str>, version: Constraint },
+    Exclude { package: Box<str> },
+    /// Excludes every package not listed by an `allow` rule.
+    Allow { packages: Vec<Box<str>> },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Exclude, Hold,
+}
+
+/// A package kept out of a repo, and the upstream version that was filtered.
+#[derive(Debug, Clone)]
+pub struct Filtered {
+    pub rule: usize,
+    pub verdict: Verdict,
+    pub version: Arc<str>,
+}
+
+impl Rule {
+    pub fn applies_to(&self, repo: &str) -> bool {
+        self.repo.as_deref().is_none_or(|v| v == repo)
+    }
+}
+
+impl Display for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(repo) = &self.repo {
+            write!(f, "{repo}: ")?;
+        }
+        match &self.action {
+            Action::Hold { package, version } => write!(f, "hold {package} {version}"),
+            Action::Exclude { package } => write!(f, "exclude {package}"),
+            Action::Allow { packages } => write!(f, "allow {}", packages.join(", ")),
+        }
+    }
+}
+
+/// Finds the first rule keeping `desc` out of `repo`, along with its index.
+pub fn check(rules: &[Rule], repo: &str, desc: &Desc) -> Option<(usize, Verdict)> {
+    let mut allow_rule = None;
+    let mut allowed = false;
+
+    for (idx, rule) in rules.iter().enumerate().filter(|(_, v)| v.applies_to(repo)) {
+        match &rule.action {
+            Action::Exclude { package } if glob::matches(package, &desc.name) => {
+                return Some((idx, Verdict::Exclude));
+            }
+            Action::Hold { package, version } if glob::matches(package, &desc.name) && !version.matches(&desc.version) => {
+                return Some((idx, Verdict::Hold));
+            }
+            Action::Allow { packages } => {
+                allow_rule.get_or_insert(idx);
+                allowed |= packages.iter().any(|v| glob::matches(v, &desc.name));
+            }
+            _ => {}
+        }
+    }
+    match allow_rule {
+        Some(idx) if !allowed => Some((idx, Verdict::Exclude)),
+        _ => None,
+    }
+}
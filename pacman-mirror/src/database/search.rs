@@ -0,0 +1,213 @@
+use std::{str::FromStr, sync::Arc};
+
+use regex::{Regex, RegexBuilder};
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::{database::{desc::Desc, package::Package, search::files::{FileIndex, FileQuery}}, Database};
+
+pub mod files;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    Exact, Prefix, Regex,
+}
+
+/// Where a package matched, best first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Field {
+    Name, Provides, Groups, Description,
+}
+
+#[derive(Debug, Error)]
+pub enum QueryError {
+    #[error("unknown search mode {0:?}, expected exact, prefix or regex")]
+    Mode(Box<str>),
+    #[error("unknown file search mode {0:?}, expected exact, glob or regex")]
+    FileMode(Box<str>),
+    #[error("invalid regex: {0}")]
+    Regex(#[from] regex::Error),
+}
+
+pub enum Query {
+    Text { mode: Mode, text: String },
+    Regex(Regex),
+}
+
+struct Entry {
+    desc: Arc<Desc>,
+    name: String,
+    provides: Vec<String>,
+    groups: Vec<String>,
+    description: String,
+}
+
+/// Lowercased names, provides, groups and descriptions of one repo's packages.
+#[derive(Default)]
+pub struct SearchIndex {
+    entries: Vec<Entry>,
+    pub files: FileIndex,
+}
+
+pub struct Hit {
+    pub desc: Arc<Desc>,
+    pub field: Field,
+    /// Whether the whole name matched, which ranks it above other matches of the same field.
+    pub exact: bool,
+}
+
+impl FromStr for Mode {
+    type Err = QueryError;
+
+    fn from_str(src: &str) -> Result<Self, Self::Err> {
+        match src {
+            "exact" => Ok(Mode::Exact),
+            "prefix" => Ok(Mode::Prefix),
+            "regex" => Ok(Mode::Regex),
+            _ => Err(QueryError::Mode(src.into())),
+        }
+    }
+}
+
+impl Query {
+    pub fn new(mode: Mode, text: &str) -> Result<Self, QueryError> {
+        Ok(match mode {
+            Mode::Regex => Query::Regex(RegexBuilder::new(text)
+                .case_insensitive(true)
+                .size_limit(1 << 20)
+                .build()?),
+            mode => Query::Text { mode, text: text.trim().to_lowercase() },
+        })
+    }
+    fn matches(&self, src: &str) -> Option<bool> {
+        match self {
+            Query::Text { mode: Mode::Prefix, text } if src.starts_with(text.as_str()) => Some(src == text),
+            Query::Text { text, .. } => (src == text).then_some(true),
+            Query::Regex(regex) => regex.find(src).map(|v| v.len() == src.len()),
+        }
+    }
+    /// Descriptions are matched word by word, unless it's a regex.
+    fn matches_text(&self, src: &str) -> bool {
+        match self {
+            Query::Regex(regex) => regex.is_match(src),
+            query => src.split(|c: char| !c.is_alphanumeric() && c != '-' && c != '_').any(|v| query.matches(v).is_some()),
+        }
+    }
+}
+
+fn lines(desc: &Desc, key: &str) -> Vec<String> {
+    desc.get(key).map(|v| v.lines().map(|v| v.to_lowercase()).collect()).unwrap_or_default()
+}
+
+impl SearchIndex {
+    pub fn new<'a>(packages: impl IntoIterator<Item = &'a Package> + Clone) -> Self {
+        let files = FileIndex::new(packages.clone());
+        let mut entries = Vec::from_iter(packages.into_iter().map(|pkg| Entry {
+            desc: pkg.desc.clone(),
+            name: pkg.desc.name.to_lowercase(),
+            // versioned provides (`libfoo.so=1-64`) are searched by name
+            provides: lines(&pkg.desc, "PROVIDES").into_iter()
+                .map(|v| v.split_once('=').map_or(v.as_str(), |v| v.0).to_owned())
+                .collect(),
+            groups: lines(&pkg.desc, "GROUPS"),
+            description: pkg.desc.get("DESC").unwrap_or("").to_lowercase(),
+        }));
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        Self { entries, files }
+    }
+    pub fn search(&self, query: &Query) -> Vec<Hit> {
+        self.entries.iter().filter_map(|entry| {
+            let (field, exact) = if let Some(exact) = query.matches(&entry.name) {
+                (Field::Name, exact)
+            } else if let Some(exact) = entry.provides.iter().find_map(|v| query.matches(v)) {
+                (Field::Provides, exact)
+            } else if let Some(exact) = entry.groups.iter().find_map(|v| query.matches(v)) {
+                (Field::Groups, exact)
+            } else if query.matches_text(&entry.description) {
+                (Field::Description, false)
+            } else {
+                return None;
+            };
+            Some(Hit { desc: entry.desc.clone(), field, exact })
+        }).collect()
+    }
+}
+
+fn rank(hits: &mut [(Arc<str>, Hit)]) {
+    hits.sort_by(|(_, a), (_, b)| a.field.cmp(&b.field)
+        .then(b.exact.cmp(&a.exact))
+        .then(a.desc.name.len().cmp(&b.desc.name.len()))
+        .then(a.desc.name.cmp(&b.desc.name)));
+}
+
+/// File search results of one repo, or `None` if it has no file lists loaded.
+pub type FileHits = (Arc<str>, Option<Vec<(Arc<Desc>, String)>>);
+
+impl Database {
+    /// Searches every repo, or only `repo`, best matches first: by field,
+    /// whole matches, then shorter names.
+    pub fn search(&self, query: &Query, repo: Option<&str>) -> Vec<(Arc<str>, Hit)> {
+        let mut hits = Vec::new();
+        for name in self.config.repo_names().filter(|v| repo.is_none_or(|repo| repo == v.as_ref())) {
+            let Some(repo) = self.repos.get(name) else {
+                continue;
+            };
+            let index = repo.search.read().unwrap().clone();
+            hits.extend(index.search(query).into_iter().map(|hit| (repo.name.clone(), hit)));
+        }
+        rank(&mut hits);
+        hits
+    }
+    pub fn search_files(&self, query: &FileQuery, repo: Option<&str>) -> Vec<FileHits> {
+        self.config.repo_names()
+            .filter(|v| repo.is_none_or(|repo| repo == v.as_ref()))
+            .filter_map(|name| self.repos.get(name))
+            .map(|repo| {
+                let index = repo.search.read().unwrap().clone();
+                let hits = (!index.files.is_empty()).then(|| index.files.search(query));
+                (repo.name.clone(), hits)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn search(mode: Mode, text: &str) -> Vec<(String, Field)> {
+        let packages = [
+            Desc::test("python", "3.12-1", &[("DESC", "The Python programming language")]),
+            Desc::test("python-pip", "24-1", &[("DESC", "The PyPA recommended tool")]),
+            Desc::test("pypy3", "7.3-1", &[("PROVIDES", "python=3.10")]),
+            Desc::test("ipython", "8.0-1", &[("DESC", "An enhanced interactive Python shell")]),
+            Desc::test("base-devel", "1-1", &[("GROUPS", "python")]),
+        ].map(Package::new);
+        let index = SearchIndex::new(packages.iter());
+        let mut hits = index.search(&Query::new(mode, text).unwrap()).into_iter()
+            .map(|hit| (Arc::from("core"), hit))
+            .collect::<Vec<_>>();
+        rank(&mut hits);
+        hits.into_iter().map(|(_, hit)| (hit.desc.name.to_string(), hit.field)).collect()
+    }
+
+    #[test]
+    fn ranks_by_field_then_whole_matches() {
+        assert_eq!(search(Mode::Exact, "Python"), [
+            ("python".into(), Field::Name),
+            ("pypy3".into(), Field::Provides),
+            ("base-devel".into(), Field::Groups),
+            ("ipython".into(), Field::Description),
+        ]);
+        assert_eq!(search(Mode::Prefix, "python").into_iter().take(3).collect::<Vec<_>>(), [
+            ("python".into(), Field::Name),
+            ("python-pip".into(), Field::Name),
+            ("pypy3".into(), Field::Provides),
+        ]);
+        // a regex matching only part of a name ranks after whole matches
+        assert_eq!(search(Mode::Regex, "^i?python$|pip").into_iter().take(3).map(|v| v.0).collect::<Vec<_>>(), ["python", "ipython", "python-pip"]);
+    }
+}
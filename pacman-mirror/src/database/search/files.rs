@@ -0,0 +1,141 @@
+use std::{collections::HashMap, sync::Arc};
+
+use regex::{Regex, RegexBuilder};
+
+use crate::{database::{desc::Desc, package::Package, search::QueryError}, glob};
+
+
+/// A path (`/usr/bin/foo`) or file name (`foo`) to look for.
+pub enum FileQuery {
+    Exact(String),
+    Glob(String),
+    Regex(Regex),
+}
+
+/// Where each file name appears in the packages' file lists.
+#[derive(Default)]
+pub struct FileIndex {
+    packages: Vec<Arc<Desc>>,
+    /// Package and byte offset into its file list, by file name.
+    by_name: HashMap<Box<str>, Vec<(u32, u32)>>,
+}
+
+fn file_name(path: &str) -> &str {
+    let path = path.trim_end_matches('/');
+    path.rsplit_once('/').map_or(path, |v| v.1)
+}
+
+impl FileQuery {
+    pub fn new(mode: &str, pattern: &str) -> Result<Self, QueryError> {
+        // file lists have no leading slash, but regexes see one
+        let path = pattern.trim().trim_start_matches('/');
+        Ok(match mode {
+            "exact" => FileQuery::Exact(path.into()),
+            "glob" => FileQuery::Glob(path.into()),
+            "regex" => FileQuery::Regex(RegexBuilder::new(pattern).size_limit(1 << 20).build()?),
+            _ => return Err(QueryError::FileMode(mode.into())),
+        })
+    }
+}
+
+impl FileIndex {
+    pub fn new<'a>(packages: impl IntoIterator<Item = &'a Package>) -> Self {
+        let mut index = Self::default();
+        for pkg in packages {
+            let Some(files) = &pkg.desc.files else {
+                continue;
+            };
+            let id = index.packages.len() as u32;
+            index.packages.push(pkg.desc.clone());
+            let mut offset = 0;
+            for line in files.split_inclusive('\n') {
+                let path = line.trim_end();
+                if !path.is_empty() && !path.starts_with('%') {
+                    index.by_name.entry(file_name(path).into()).or_default().push((id, offset as u32));
+                }
+                offset += line.len();
+            }
+        }
+        index
+    }
+    /// Whether any package had a file list, which only `.files` refreshes have.
+    pub fn is_empty(&self) -> bool {
+        self.packages.is_empty()
+    }
+    fn path(&self, id: u32, offset: u32) -> &str {
+        let files = self.packages[id as usize].files.as_deref().unwrap_or("");
+        files[offset as usize..].lines().next().unwrap_or("").trim_end()
+    }
+    /// Matching packages and the absolute paths that matched.
+    pub fn search(&self, query: &FileQuery) -> Vec<(Arc<Desc>, String)> {
+        let names: Vec<&Vec<(u32, u32)>> = match query {
+            FileQuery::Exact(pattern) | FileQuery::Glob(pattern) => {
+                let name = file_name(pattern);
+                match query {
+                    FileQuery::Glob(_) if name.contains(['*', '?']) => self.by_name.iter()
+                        .filter(|(key, _)| glob::matches(name, key))
+                        .map(|(_, v)| v)
+                        .collect(),
+                    _ => self.by_name.get(name).into_iter().collect(),
+                }
+            }
+            FileQuery::Regex(_) => self.by_name.values().collect(),
+        };
+        let mut hits = Vec::new();
+        let mut absolute = String::new();
+        for &(id, offset) in names.into_iter().flatten() {
+            let path = self.path(id, offset);
+            absolute.clear();
+            absolute.push('/');
+            absolute.push_str(path);
+            let matched = match query {
+                // a bare name matches wherever it's installed
+                FileQuery::Exact(pattern) => !pattern.contains('/') || path.trim_end_matches('/') == pattern.trim_end_matches('/'),
+                FileQuery::Glob(pattern) => !pattern.contains('/') || glob::matches(pattern.trim_end_matches('/'), path.trim_end_matches('/')),
+                FileQuery::Regex(regex) => regex.is_match(&absolute),
+            };
+            if matched {
+                hits.push((self.packages[id as usize].clone(), absolute.clone()));
+            }
+        }
+        hits.sort_by(|a, b| a.0.name.cmp(&b.0.name).then_with(|| a.1.cmp(&b.1)));
+        hits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index() -> FileIndex {
+        let packages = [
+            ("coreutils", "%FILES%\nusr/\nusr/bin/\nusr/bin/ls\nusr/bin/cat\n"),
+            ("busybox", "%FILES%\nusr/\nusr/lib/busybox/\nusr/lib/busybox/ls\n"),
+            ("empty", "%FILES%\n"),
+        ].map(|(name, files)| {
+            let mut desc = Arc::into_inner(Desc::test(name, "1-1", &[])).unwrap();
+            desc.files = Some(files.into());
+            Package::new(Arc::new(desc))
+        });
+        FileIndex::new(packages.iter())
+    }
+
+    fn search(mode: &str, pattern: &str) -> Vec<(String, String)> {
+        index().search(&FileQuery::new(mode, pattern).unwrap()).into_iter()
+            .map(|(desc, path)| (desc.name.to_string(), path))
+            .collect()
+    }
+
+    #[test]
+    fn names_paths_and_patterns() {
+        assert_eq!(search("exact", "ls"), [("busybox".into(), "/usr/lib/busybox/ls".into()), ("coreutils".into(), "/usr/bin/ls".into())]);
+        assert_eq!(search("exact", "/usr/bin/ls"), [("coreutils".into(), "/usr/bin/ls".into())]);
+        assert_eq!(search("exact", "usr/bin/"), [("coreutils".into(), "/usr/bin/".into())]);
+        assert_eq!(search("glob", "/usr/*/c?t"), [("coreutils".into(), "/usr/bin/cat".into())]);
+        assert_eq!(search("regex", "^/usr/lib/.+/ls$"), [("busybox".into(), "/usr/lib/busybox/ls".into())]);
+        assert!(search("exact", "missing").is_empty());
+        assert!(FileQuery::new("fuzzy", "ls").is_err());
+        assert!(!index().is_empty());
+        assert!(FileIndex::default().is_empty());
+    }
+}
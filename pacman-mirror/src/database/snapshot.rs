@@ -0,0 +1,367 @@
+use std::{collections::{BTreeMap, HashMap, HashSet}, fs::File, io::{BufReader, BufWriter}, ops::Bound, path::{Path, PathBuf}, sync::{atomic::{AtomicU64, Ordering}, Arc, Mutex}, time::Duration};
+
+use anyhow::Context;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{cache::Cache, config::duration, database::{archive, package::Package, repo::state::FetchType, Repo}, date::DateTime, Config};
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotConfig {
+    pub dir: PathBuf,
+    /// How long a snapshot is kept once a newer one exists.
+    #[serde(with = "duration")]
+    pub retention: Duration,
+}
+
+/// The packages a repo served after one of its refreshes.
+pub struct Snapshot {
+    pub id: Arc<str>,
+    pub created: DateTime,
+    pub ty: FetchType,
+    /// Packages by filename.
+    pub packages: HashMap<Arc<str>, Package>,
+}
+
+impl Snapshot {
+    pub fn sorted(&self) -> Vec<&Package> {
+        let mut packages = Vec::from_iter(self.packages.values());
+        packages.sort_by(|a, b| a.desc.name.cmp(&b.desc.name));
+        packages
+    }
+}
+
+type Entries = BTreeMap<Arc<str>, Entry>;
+
+struct Entry {
+    ty: FetchType,
+    loaded: Option<Arc<Snapshot>>,
+    /// When `loaded` was last asked for, counted in calls to `get`.
+    used: u64,
+    /// Files whose caches it holds, once it was recorded or parsed.
+    files: Option<Vec<Arc<str>>>,
+}
+
+/// The snapshots of one repo.
+#[derive(Default)]
+struct Stored {
+    entries: Entries,
+    /// Caches of the files in snapshots, with how many snapshots hold each one.
+    /// They stay once a package leaves the repo, as mirrors soon delete old versions.
+    caches: HashMap<Arc<str>, (Arc<Cache>, usize)>,
+}
+
+/// How many parsed snapshots of a repo are kept in memory, the least recently used are dropped first.
+const MAX_LOADED: usize = 4;
+
+impl Stored {
+    fn evict(&mut self) {
+        let mut loaded = self.entries.values_mut().filter(|v| v.loaded.is_some()).collect::<Vec<_>>();
+        if loaded.len() > MAX_LOADED {
+            loaded.sort_by_key(|v| std::cmp::Reverse(v.used));
+            for entry in loaded.into_iter().skip(MAX_LOADED) {
+                entry.loaded = None;
+            }
+        }
+    }
+    /// Keeps the caches of `packages` for as long as the snapshot `id` is retained.
+    fn hold<'a>(&mut self, id: &str, packages: impl IntoIterator<Item = &'a Package>) {
+        let Some(entry) = self.entries.get_mut(id).filter(|v| v.files.is_none()) else {
+            return;
+        };
+        let mut files = Vec::new();
+        for package in packages {
+            self.caches.entry(package.desc.filename.clone()).or_insert_with(|| (package.cache.clone(), 0)).1 += 1;
+            files.push(package.desc.filename.clone());
+        }
+        entry.files = Some(files);
+    }
+    fn release(&mut self, entry: Entry) {
+        for filename in entry.files.into_iter().flatten() {
+            if let Some((_, count)) = self.caches.get_mut(&filename) {
+                *count -= 1;
+                if *count == 0 {
+                    self.caches.remove(&filename);
+                }
+            }
+        }
+    }
+}
+
+/// Snapshots are stored as `{dir}/{repo}/{id}.{db,files}.tar.gz` and only parsed once something asks for them.
+pub struct Snapshots {
+    config: Option<SnapshotConfig>,
+    repos: Mutex<HashMap<Arc<str>, Stored>>,
+    pinned: Mutex<HashSet<(Arc<str>, Arc<str>)>>,
+    uses: AtomicU64,
+}
+
+fn type_name(ty: FetchType) -> &'static str {
+    match ty {
+        FetchType::Db => "db",
+        FetchType::Files => "files",
+    }
+}
+
+fn scan(dir: &Path) -> std::io::Result<Entries> {
+    let mut entries = BTreeMap::new();
+    let read_dir = match std::fs::read_dir(dir) {
+        Ok(v) => v,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(entries),
+        Err(err) => return Err(err),
+    };
+    for item in read_dir {
+        let name = item?.file_name();
+        let Some((id, ty)) = name.to_str()
+            .and_then(|v| v.strip_suffix(".tar.gz"))
+            .and_then(|v| v.split_once('.'))
+            .filter(|(id, _)| DateTime::parse_id(id).is_some()) else {
+            continue;
+        };
+        let ty = match ty {
+            "db" => FetchType::Db,
+            "files" => FetchType::Files,
+            _ => continue,
+        };
+        entries.insert(id.into(), Entry { ty, loaded: None, used: 0, files: None });
+    }
+    Ok(entries)
+}
+
+impl Snapshots {
+    pub fn new(config: &Config) -> Self {
+        let mut repos = HashMap::new();
+        if let Some(snapshots) = &config.snapshots {
+            for name in config.repo_names() {
+                match scan(&snapshots.dir.join(name.as_ref())) {
+                    Ok(entries) => _ = repos.insert(name.clone(), Stored { entries, caches: HashMap::new() }),
+                    Err(err) => warn!("Failed to read snapshots of {name}: {err}"),
+                }
+            }
+        }
+        Self { config: config.snapshots.clone(), repos: Mutex::new(repos), pinned: Mutex::default(), uses: AtomicU64::new(0) }
+    }
+    fn path(config: &SnapshotConfig, repo: &str, id: &str, ty: FetchType) -> PathBuf {
+        config.dir.join(repo).join(format!("{id}.{}.tar.gz", type_name(ty)))
+    }
+    pub fn dir(&self) -> Option<&Path> {
+        self.config.as_ref().map(|v| v.dir.as_path())
+    }
+    /// Snapshot ids of a repo, oldest first.
+    pub fn list(&self, repo: &str) -> Vec<Arc<str>> {
+        self.repos.lock().unwrap().get(repo)
+            .map(|v| v.entries.keys().cloned().collect())
+            .unwrap_or_default()
+    }
+    pub fn get(&self, repo: &Repo, id: &str) -> anyhow::Result<Option<Arc<Snapshot>>> {
+        let Some(config) = &self.config else {
+            return Ok(None);
+        };
+        let (id, ty) = {
+            let mut repos = self.repos.lock().unwrap();
+            let Some(entry) = repos.get_mut(&repo.name).and_then(|v| v.entries.get_mut(id)) else {
+                return Ok(None);
+            };
+            if let Some(snapshot) = &entry.loaded {
+                entry.used = self.uses.fetch_add(1, Ordering::Relaxed);
+                return Ok(Some(snapshot.clone()));
+            }
+            (Arc::<str>::from(id), entry.ty)
+        };
+        let snapshot = Arc::new(self.load(config, repo, id.clone(), ty)?);
+        let mut repos = self.repos.lock().unwrap();
+        let Some(stored) = repos.get_mut(&repo.name) else {
+            return Ok(Some(snapshot));
+        };
+        let snapshot = match stored.entries.get_mut(&id) {
+            Some(entry) => {
+                entry.used = self.uses.fetch_add(1, Ordering::Relaxed);
+                entry.loaded.get_or_insert(snapshot).clone()
+            }
+            None => return Ok(Some(snapshot)),
+        };
+        stored.hold(&id, snapshot.packages.values());
+        stored.evict();
+        Ok(Some(snapshot))
+    }
+    /// The last snapshot taken on or before `date`.
+    pub fn find(&self, repo: &Repo, date: DateTime) -> anyhow::Result<Option<Arc<Snapshot>>> {
+        let end = date.add_days(1).id();
+        let id = self.repos.lock().unwrap().get(&repo.name)
+            .and_then(|v| v.entries.range::<str, _>((Bound::Unbounded, Bound::Excluded(end.as_str()))).next_back().map(|(id, _)| id.clone()));
+        match id {
+            Some(id) => self.get(repo, &id),
+            None => Ok(None),
+        }
+    }
+    fn load(&self, config: &SnapshotConfig, repo: &Repo, id: Arc<str>, ty: FetchType) -> anyhow::Result<Snapshot> {
+        let path = Self::path(config, &repo.name, &id, ty);
+        let created = DateTime::parse_id(&id).context("Invalid snapshot id")?;
+        let file = File::open(&path).with_context(|| format!("Failed to open {}", path.display()))?;
+        let mirrors = Vec::from_iter(repo.mirrors.iter().chain(repo.overlays.iter()).cloned());
+
+        // share caches with whatever else serves the same file
+        let mut caches = self.repos.lock().unwrap().get(&repo.name)
+            .map(|v| v.caches.iter().map(|(filename, (cache, _))| (filename.clone(), cache.clone())).collect::<HashMap<_, _>>())
+            .unwrap_or_default();
+        for package in repo.state.read().unwrap().packages.values() {
+            caches.insert(package.desc.filename.clone(), package.cache.clone());
+        }
+
+        let mut packages = HashMap::new();
+        archive::read(BufReader::new(file), ty, |desc| {
+            let mut package = Package::new(desc);
+            if let Some(cache) = caches.get(&package.desc.filename) {
+                package.cache = cache.clone();
+            }
+            package.mirrors = mirrors.clone();
+            packages.insert(package.desc.filename.clone(), package);
+        })?;
+        Ok(Snapshot { id, created, ty, packages })
+    }
+    /// Snapshots that outlive their retention, e.g. because a channel points at them.
+    pub fn set_pinned(&self, pinned: HashSet<(Arc<str>, Arc<str>)>) {
+        *self.pinned.lock().unwrap() = pinned;
+    }
+    /// Stores what the repo serves now, unless it's the same as its last snapshot.
+    /// Returns the id of the snapshot matching the current state.
+    pub fn record(&self, repo: &Repo) -> anyhow::Result<Option<Arc<str>>> {
+        self.record_at(repo, DateTime::now())
+    }
+    pub(super) fn record_at(&self, repo: &Repo, created: DateTime) -> anyhow::Result<Option<Arc<str>>> {
+        let Some(config) = &self.config else {
+            return Ok(None);
+        };
+        let snapshot = {
+            let state = repo.state.read().unwrap();
+            Snapshot {
+                id: created.id().into(),
+                created,
+                ty: state.ty,
+                packages: state.packages.values()
+                    .map(|pkg| (pkg.desc.filename.clone(), pkg.clone()))
+                    .collect(),
+            }
+        };
+        let latest = self.list(&repo.name).pop()
+            .and_then(|id| self.get(repo, &id).inspect_err(|err| warn!("{err:?}")).ok().flatten());
+        if let Some(latest) = latest
+            && latest.ty == snapshot.ty
+            && latest.packages.keys().collect::<HashSet<_>>() == snapshot.packages.keys().collect() {
+            return Ok(Some(latest.id.clone()));
+        }
+
+        let path = Self::path(config, &repo.name, &snapshot.id, snapshot.ty);
+        let part = path.with_extension("part");
+        std::fs::create_dir_all(config.dir.join(repo.name.as_ref()))?;
+        let mut writer = archive::Writer::new(BufWriter::new(File::create(&part)?), snapshot.ty, 6);
+        for package in snapshot.sorted() {
+            writer.append(&package.desc)?;
+        }
+        writer.finish()?.into_inner()?.sync_all()?;
+        std::fs::rename(&part, &path)?;
+        info!("Saved snapshot {}/{}: {} packages", repo.name, snapshot.id, snapshot.packages.len());
+
+        let id = snapshot.id.clone();
+        let pinned = self.pinned.lock().unwrap();
+        let mut repos = self.repos.lock().unwrap();
+        let stored = repos.entry(repo.name.clone()).or_default();
+        let ty = snapshot.ty;
+        if let Some(replaced) = stored.entries.insert(id.clone(), Entry { ty, loaded: None, used: 0, files: None }) {
+            if replaced.ty != ty {
+                _ = std::fs::remove_file(Self::path(config, &repo.name, &id, replaced.ty));
+            }
+            stored.release(replaced);
+        }
+        stored.hold(&id, snapshot.packages.values());
+
+        // a retention reaching before the epoch never expires anything
+        let Some(cutoff) = created.to_system().checked_sub(config.retention) else {
+            return Ok(Some(id));
+        };
+        let cutoff = DateTime::from_system(cutoff).id();
+        // the newest one is kept however old it gets
+        let expired = stored.entries.range::<str, _>((Bound::Unbounded, Bound::Excluded(cutoff.as_str())))
+            .map(|(id, _)| id.clone())
+            .filter(|id| Some(id) != stored.entries.keys().next_back())
+            .filter(|id| !pinned.contains(&(repo.name.clone(), id.clone())))
+            .collect::<Vec<_>>();
+        for id in expired {
+            let Some(entry) = stored.entries.remove(&id) else {
+                continue;
+            };
+            let path = Self::path(config, &repo.name, &id, entry.ty);
+            stored.release(entry);
+            match std::fs::remove_file(&path) {
+                Ok(()) => info!("Removed snapshot {}/{id}", repo.name),
+                Err(err) => warn!("Failed to remove {}: {err}", path.display()),
+            }
+        }
+        Ok(Some(id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use replay_buffer::ReplayBufferWriter;
+
+    use crate::{cache::DataSource, database::{desc::Desc, mirror::Mirror, prefetch::Prefetcher}};
+
+    use super::*;
+
+    fn repo(dir: &Path) -> Repo {
+        let config = Arc::new(Config {
+            mirrors: vec![Mirror::new("https://a/$repo".into())],
+            snapshots: Some(SnapshotConfig { dir: dir.into(), retention: Duration::from_secs(3600) }),
+            ..Config::default()
+        });
+        Repo::empty(config.clone(), Arc::new(Snapshots::new(&config)), Arc::new(Prefetcher::new(&config)), "core".into(), Vec::new())
+    }
+
+    /// Serves `descs`, keeping the packages already served like a refresh does.
+    fn serve(repo: &Repo, descs: &[Arc<Desc>]) {
+        let mut state = repo.state.write().unwrap();
+        state.packages = descs.iter()
+            .map(|desc| match state.packages.get(&desc.name).filter(|v| v.desc.filename == desc.filename) {
+                Some(pkg) => (desc.name.clone(), pkg.clone()),
+                None => (desc.name.clone(), Package::new(desc.clone())),
+            })
+            .collect();
+    }
+
+    #[test]
+    fn keeps_caches_of_retained_snapshots() {
+        let dir = std::env::temp_dir().join(format!("pacman-mirror-snapshots-{}", std::process::id()));
+        let repo = repo(&dir);
+        let snapshots = &repo.snapshots;
+        let start = DateTime::now().to_system();
+        let at = |mins: u64| DateTime::from_system(start + Duration::from_secs(mins * 60));
+
+        let old = Desc::test("foo", "1-1", &[]);
+        serve(&repo, std::slice::from_ref(&old));
+        let writer = ReplayBufferWriter::new();
+        writer.extend(*b"package");
+        repo.state.read().unwrap().packages["foo"].cache.set(DataSource::Memory(writer.source().clone()));
+        drop(writer);
+        let first = snapshots.record_at(&repo, at(0)).unwrap().unwrap();
+
+        // foo-1 leaves the repo, then more snapshots than are kept parsed are taken
+        for i in 1..=MAX_LOADED as u64 + 2 {
+            serve(&repo, &[Desc::test("foo", &format!("{}-1", i + 1), &[])]);
+            snapshots.record_at(&repo, at(i)).unwrap();
+        }
+        assert!(snapshots.repos.lock().unwrap()["core"].entries[&first].loaded.is_none());
+        let snapshot = snapshots.get(&repo, &first).unwrap().unwrap();
+        assert!(matches!(snapshot.packages[&old.filename].cache.get(), DataSource::Memory(_)));
+        drop(snapshot);
+
+        // until it expires
+        serve(&repo, &[Desc::test("bar", "1-1", &[])]);
+        snapshots.record_at(&repo, at(120)).unwrap();
+        let stored = &snapshots.repos.lock().unwrap()["core"];
+        assert_eq!(stored.entries.len(), 1);
+        assert!(!stored.caches.contains_key(&old.filename));
+        _ = std::fs::remove_dir_all(dir);
+    }
+}
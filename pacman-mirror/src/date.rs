@@ -0,0 +1,114 @@
+use std::{fmt::Display, time::{Duration, SystemTime}};
+
+const DAY: i64 = 86400;
+
+
+/// A UTC timestamp, split into calendar fields.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DateTime {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+}
+
+// http://howardhinnant.github.io/date_algorithms.html
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = if month > 2 { month - 3 } else { month + 9 } as i64;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+impl DateTime {
+    pub fn from_secs(secs: i64) -> Self {
+        let (year, month, day) = civil_from_days(secs.div_euclid(DAY));
+        let secs = secs.rem_euclid(DAY) as u32;
+        Self { year, month, day, hour: secs / 3600, minute: secs / 60 % 60, second: secs % 60 }
+    }
+    pub fn secs(&self) -> i64 {
+        days_from_civil(self.year, self.month, self.day) * DAY
+            + (self.hour * 3600 + self.minute * 60 + self.second) as i64
+    }
+    pub fn from_system(time: SystemTime) -> Self {
+        Self::from_secs(match time.duration_since(SystemTime::UNIX_EPOCH) {
+            Ok(v) => v.as_secs() as i64,
+            Err(err) => -(err.duration().as_secs() as i64),
+        })
+    }
+    pub fn to_system(self) -> SystemTime {
+        let secs = self.secs();
+        match secs >= 0 {
+            true => SystemTime::UNIX_EPOCH + Duration::from_secs(secs as u64),
+            false => SystemTime::UNIX_EPOCH - Duration::from_secs(secs.unsigned_abs()),
+        }
+    }
+    pub fn now() -> Self {
+        Self::from_system(SystemTime::now())
+    }
+    fn checked(self) -> Option<Self> {
+        let valid = self.month >= 1 && self.hour < 24 && self.minute < 60 && self.second < 60
+            && civil_from_days(days_from_civil(self.year, self.month, self.day)) == (self.year, self.month, self.day);
+        valid.then_some(self)
+    }
+    /// Parses a `YYYY-MM-DD` date, at midnight.
+    pub fn parse_date(src: &str) -> Option<Self> {
+        let mut parts = src.splitn(3, '-');
+        let mut next = || parts.next().filter(|v| !v.is_empty() && v.bytes().all(|c| c.is_ascii_digit()));
+        let (year, month, day) = (next()?.parse().ok()?, next()?.parse().ok()?, next()?.parse().ok()?);
+        Self { year, month, day, hour: 0, minute: 0, second: 0 }.checked()
+    }
+    pub fn date(&self) -> String {
+        format!("{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+    /// A compact form that sorts in time order, usable in file names (`YYYYMMDDTHHMMSSZ`).
+    pub fn id(&self) -> String {
+        format!("{:04}{:02}{:02}T{:02}{:02}{:02}Z", self.year, self.month, self.day, self.hour, self.minute, self.second)
+    }
+    pub fn parse_id(src: &str) -> Option<Self> {
+        let src = src.strip_suffix('Z')?;
+        let (date, time) = src.split_once('T')?;
+        if date.len() != 8 || time.len() != 6 || !date.bytes().chain(time.bytes()).all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        fn num<T: std::str::FromStr>(src: &str, at: usize, len: usize) -> Option<T> {
+            src[at..at + len].parse().ok()
+        }
+        Self {
+            year: num(date, 0, 4)?,
+            month: num(date, 4, 2)?,
+            day: num(date, 6, 2)?,
+            hour: num(time, 0, 2)?,
+            minute: num(time, 2, 2)?,
+            second: num(time, 4, 2)?,
+        }.checked()
+    }
+    pub fn add_days(self, days: i64) -> Self {
+        Self::from_secs(self.secs() + days * DAY)
+    }
+}
+
+/// RFC 3339, e.g. `2024-05-01T12:00:00Z`.
+impl Display for DateTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}T{:02}:{:02}:{:02}Z", self.date(), self.hour, self.minute, self.second)
+    }
+}
@@ -0,0 +1,48 @@
+/// Matches `text` against a shell-style pattern, where `*` matches any run of
+/// characters and `?` matches a single character.
+pub fn matches(pattern: &str, text: &str) -> bool {
+    let (pattern, text) = (pattern.as_bytes(), text.as_bytes());
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == b'?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((bp, bt)) => {
+                    p = bp + 1;
+                    t = bt + 1;
+                    backtrack = Some((bp, bt + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patterns() {
+        assert!(matches("linux", "linux"));
+        assert!(!matches("linux", "linux-lts"));
+        assert!(matches("linux*", "linux-lts"));
+        assert!(matches("*-git", "foo-git"));
+        assert!(matches("nvidia-*-dkms", "nvidia-open-dkms"));
+        assert!(!matches("nvidia-*-dkms", "nvidia-dkms"));
+        assert!(matches("lib?", "libx"));
+        assert!(!matches("lib?", "lib"));
+        assert!(matches("*", ""));
+        assert!(matches("a*b*c", "aXbYbZc"));
+    }
+}
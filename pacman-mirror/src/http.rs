@@ -0,0 +1,108 @@
+use std::{io::{self, Read}, sync::mpsc, time::Duration};
+
+use anyhow::bail;
+use base64::{prelude::BASE64_STANDARD, Engine};
+use log::trace;
+
+use crate::database::mirror::{Auth, Mirror};
+
+
+/// How many chunks a response reads ahead of its reader.
+const READ_AHEAD: usize = 4;
+
+/// A response body that fails once the mirror sends nothing for `read_timeout`.
+pub struct Body {
+    chunks: mpsc::Receiver<io::Result<Vec<u8>>>,
+    chunk: Vec<u8>,
+    at: usize,
+    read_timeout: Duration,
+}
+
+impl Read for Body {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.at == self.chunk.len() {
+            self.chunk = match self.chunks.recv_timeout(self.read_timeout) {
+                Ok(chunk) => chunk?,
+                Err(mpsc::RecvTimeoutError::Disconnected) => return Ok(0),
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    return Err(io::Error::new(io::ErrorKind::TimedOut, format!("no data received for {:?}", self.read_timeout)));
+                }
+            };
+            self.at = 0;
+        }
+        let len = buf.len().min(self.chunk.len() - self.at);
+        buf[..len].copy_from_slice(&self.chunk[self.at..self.at + len]);
+        self.at += len;
+        Ok(len)
+    }
+}
+
+/// Sends a GET request to `url` with the connection settings of `mirror`.
+///
+/// minreq only supports a single deadline for a whole request, which has to
+/// allow for long downloads, so the request runs on its own thread instead.
+/// It fails if the response doesn't start within `connect_timeout`, or if
+/// the body then stalls for `read_timeout`. The thread itself gives up at
+/// the `download_timeout` deadline, so a connection that hangs doesn't keep
+/// it forever.
+pub fn get(mirror: &Mirror, url: &str) -> anyhow::Result<Body> {
+    trace!("GET {url}");
+    // minreq only takes whole seconds
+    let mut req = minreq::get(url).with_timeout(mirror.download_timeout().as_secs().max(1));
+
+    for (k, v) in mirror.headers.iter() {
+        req = req.with_header(k.as_ref(), v.as_ref());
+    }
+    match &mirror.auth {
+        Some(Auth::Basic { username, password }) => {
+            let token = BASE64_STANDARD.encode(format!("{username}:{}", password.expose()));
+            req = req.with_header("Authorization", format!("Basic {token}"));
+        }
+        Some(Auth::Bearer { token }) => {
+            req = req.with_header("Authorization", format!("Bearer {}", token.expose()));
+        }
+        None => {}
+    }
+    if let Some(proxy) = &mirror.proxy {
+        req = req.with_proxy(minreq::Proxy::new(proxy.as_ref())?);
+    }
+
+    let (started_tx, started) = mpsc::channel();
+    let (chunks_tx, chunks) = mpsc::sync_channel(READ_AHEAD);
+    std::thread::spawn(move || {
+        let mut res = match req.send_lazy() {
+            Ok(res) if res.status_code == 200 => res,
+            Ok(res) => {
+                _ = started_tx.send(Err(anyhow::anyhow!("failed with code {}: {}", res.status_code, res.reason_phrase)));
+                return;
+            }
+            Err(err) => {
+                _ = started_tx.send(Err(err.into()));
+                return;
+            }
+        };
+        if started_tx.send(Ok(())).is_err() {
+            return;
+        }
+        loop {
+            let mut chunk = vec![0u8; 16384];
+            let chunk = match res.read(&mut chunk) {
+                Ok(0) => return,
+                Ok(len) => Ok(chunk[..len].to_vec()),
+                Err(err) => Err(err),
+            };
+            let failed = chunk.is_err();
+            // stops once the reader is gone
+            if chunks_tx.send(chunk).is_err() || failed {
+                return;
+            }
+        }
+    });
+
+    match started.recv_timeout(mirror.connect_timeout()) {
+        Ok(Ok(())) => Ok(Body { chunks, chunk: Vec::new(), at: 0, read_timeout: mirror.read_timeout() }),
+        Ok(Err(err)) => bail!("Request {url} {err}"),
+        Err(mpsc::RecvTimeoutError::Disconnected) => bail!("Request {url} failed"),
+        Err(mpsc::RecvTimeoutError::Timeout) => bail!("Request {url} timed out after {:?}", mirror.connect_timeout()),
+    }
+}
@@ -1,27 +1,43 @@
+pub mod admin;
 pub mod database;
 pub mod package;
 pub mod property;
 pub mod repo_list;
 pub mod package_list;
+pub mod package_page;
 pub mod item;
+pub mod auth;
+pub mod upload;
+pub mod snapshot;
+pub mod channel;
+pub mod api;
+pub mod search;
+pub mod files;
+pub mod health;
+pub mod metrics;
+pub mod graph;
+pub mod plan;
+pub mod prefetch;
+pub mod changelog;
 
 use std::sync::Arc;
 
 use maud::html;
 
-use crate::{Config, Database};
+use crate::{cache::Downloads, Config, Database};
 
 pub use property::get_property;
 
 pub struct Index {
     db: Arc<Database>,
     config: Arc<Config>,
+    downloads: Arc<Downloads>,
 }
 
 impl Index {
     pub fn new(db: Arc<Database>) -> Self {
         let config = db.config.clone();
-        Self { db, config }
+        Self { db, config, downloads: Arc::default() }
     }
 }
 
@@ -37,6 +53,11 @@ fn template(path: &str, body: maud::Markup) -> maud::Markup {
             table {
                 border: 1px black solid;
             }
+            .warning {
+                background: #fed;
+                border: 1px #c60 solid;
+                padding: 0.5em;
+            }
             "# }
         }
         body {
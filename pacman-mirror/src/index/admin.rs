@@ -0,0 +1,205 @@
+use std::fs::File;
+
+use log::warn;
+use rouille::{Request, Response};
+use serde::Serialize;
+use sha2::Digest;
+
+use crate::{cache::DataSource, database::{package::Package, prefetch::{Job, Reason}, repo::state::FetchType}, glob, Index};
+
+use super::auth;
+
+
+#[derive(Serialize)]
+struct Refreshed<'a> {
+    repo: &'a str,
+    ty: FetchType,
+    packages: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    degraded: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Purged {
+    purged: usize,
+}
+
+#[derive(Serialize)]
+struct Quarantined<'a> {
+    quarantined: bool,
+    repos: Vec<&'a str>,
+}
+
+#[derive(Serialize)]
+struct Queued {
+    queued: usize,
+}
+
+#[derive(Serialize)]
+struct Verified {
+    verified: usize,
+    /// Cache entries that didn't match their checksum or couldn't be read, which were purged.
+    failed: Vec<String>,
+    /// Downloads still in progress.
+    skipped: usize,
+}
+
+#[derive(Serialize)]
+struct DownloadInfo<'a> {
+    filename: &'a str,
+    url: &'a str,
+    size: usize,
+    received: usize,
+    readers: usize,
+    started: String,
+}
+
+/// Whether the cached data of `package` matches its checksum, or `None` if nothing complete is cached.
+fn verify(package: &Package) -> anyhow::Result<Option<bool>> {
+    let mut hasher = sha2::Sha256::new();
+    match package.cache.get() {
+        DataSource::Empty => return Ok(None),
+        DataSource::Memory(buffer) if buffer.is_writing() => return Ok(None),
+        DataSource::Memory(buffer) => std::io::copy(&mut buffer.read(), &mut hasher)?,
+        DataSource::File(path) => std::io::copy(&mut File::open(&path)?, &mut hasher)?,
+    };
+    Ok(Some(hasher.finalize().as_slice() == package.desc.sha256sum))
+}
+
+impl Index {
+    /// Packages of `repo` matching `?package=`, or all of them if it's missing.
+    fn admin_packages(&self, req: &Request, repo: &str) -> Result<Vec<Package>, Response> {
+        auth::check_bearer(req, &self.config.admin_tokens)?;
+        let Some(repo) = self.db.repos.get(repo) else {
+            return Err(Response::empty_404());
+        };
+        let pattern = req.get_param("package");
+        let packages = repo.state.read().unwrap().packages.values()
+            .filter(|pkg| pattern.as_deref().is_none_or(|v| glob::matches(v, &pkg.desc.name)))
+            .cloned()
+            .collect::<Vec<_>>();
+        if packages.is_empty() && pattern.is_some() {
+            return Err(Response::text("No package matches\n").with_status_code(404));
+        }
+        Ok(packages)
+    }
+    /// Refreshes a repo straight away, as `?type=db` or `files` (the type it was
+    /// last refreshed with by default), and responds once it's done.
+    pub fn admin_refresh(&self, req: &Request, repo: String) -> Response {
+        if let Err(res) = auth::check_bearer(req, &self.config.admin_tokens) {
+            return res;
+        }
+        let Some(repo) = self.db.repos.get(repo.as_str()) else {
+            return Response::empty_404();
+        };
+        let ty = match req.get_param("type").as_deref() {
+            None => repo.state.read().unwrap().ty,
+            Some("db") => FetchType::Db,
+            Some("files") => FetchType::Files,
+            Some(src) => return Response::text(format!("Invalid value for type: {src:?}, expected db or files\n")).with_status_code(400),
+        };
+        repo.refresh_now(ty);
+        let state = repo.state.read().unwrap();
+        Response::json(&Refreshed {
+            repo: &repo.name,
+            ty: state.ty,
+            packages: state.packages.len(),
+            degraded: state.degraded.as_ref().map(|v| v.to_string()),
+        })
+    }
+    /// Drops cached packages so they're fetched again on the next request.
+    /// Downloads already running carry on for the clients reading them.
+    pub fn admin_purge(&self, req: &Request, repo: String) -> Response {
+        let packages = match self.admin_packages(req, &repo) {
+            Ok(packages) => packages,
+            Err(res) => return res,
+        };
+        let mut purged = 0;
+        for package in packages.iter().filter(|v| !matches!(v.cache.get(), DataSource::Empty)) {
+            package.cache.set(DataSource::Empty);
+            purged += 1;
+        }
+        Response::json(&Purged { purged })
+    }
+    /// Checks cached packages against their checksums, purging the ones that don't
+    /// match or can't be read.
+    pub fn admin_verify(&self, req: &Request, repo: String) -> Response {
+        let packages = match self.admin_packages(req, &repo) {
+            Ok(packages) => packages,
+            Err(res) => return res,
+        };
+        let mut result = Verified { verified: 0, failed: Vec::new(), skipped: 0 };
+        for package in packages.iter() {
+            match verify(package) {
+                Ok(Some(true)) => result.verified += 1,
+                Ok(Some(false)) => {
+                    package.cache.set(DataSource::Empty);
+                    result.failed.push(package.desc.filename.to_string());
+                }
+                Ok(None) if matches!(package.cache.get(), DataSource::Memory(_)) => result.skipped += 1,
+                Ok(None) => (),
+                Err(err) => {
+                    warn!("Failed to verify {}: {err}", package.desc.filename);
+                    package.cache.set(DataSource::Empty);
+                    result.failed.push(package.desc.filename.to_string());
+                }
+            }
+        }
+        Response::json(&result)
+    }
+    /// Stops or resumes using the mirror `?url=` (as written in the config) for every repo.
+    pub fn admin_quarantine(&self, req: &Request, quarantined: bool) -> Response {
+        if let Err(res) = auth::check_bearer(req, &self.config.admin_tokens) {
+            return res;
+        }
+        let Some(url) = req.get_param("url") else {
+            return Response::text("Missing url\n").with_status_code(400);
+        };
+        let mut repos = Vec::new();
+        for repo in self.config.repo_names().filter_map(|name| self.db.repos.get(name)) {
+            for mirror in repo.mirrors.iter().filter(|mirror| mirror.mirror.url() == url) {
+                mirror.set_quarantined(quarantined);
+                repos.push(repo.name.as_ref());
+            }
+        }
+        if repos.is_empty() {
+            return Response::text("No mirror has this url\n").with_status_code(404);
+        }
+        Response::json(&Quarantined { quarantined, repos })
+    }
+    /// Queues the packages matching `?package=` in `?repo=`, or in every repo, for prefetching.
+    pub fn admin_prefetch(&self, req: &Request) -> Response {
+        if let Err(res) = auth::check_bearer(req, &self.config.admin_tokens) {
+            return res;
+        }
+        let Some(pattern) = req.get_param("package") else {
+            return Response::text("Missing package\n").with_status_code(400);
+        };
+        let repo = req.get_param("repo");
+        let mut jobs = Vec::new();
+        for repo in self.config.repo_names().filter(|v| repo.as_deref().is_none_or(|repo| repo == v.as_ref())).filter_map(|v| self.db.repos.get(v)) {
+            jobs.extend(repo.state.read().unwrap().packages.values()
+                .filter(|pkg| glob::matches(&pattern, &pkg.desc.name))
+                .filter(|pkg| matches!(pkg.cache.get(), DataSource::Empty))
+                .map(|pkg| Job { repo: repo.name.clone(), package: pkg.clone(), reason: Reason::Admin }));
+        }
+        let queued = jobs.len();
+        self.db.prefetch.push(jobs);
+        Response::json(&Queued { queued })
+    }
+    /// Packages being fetched from mirrors right now.
+    pub fn admin_downloads(&self, req: &Request) -> Response {
+        if let Err(res) = auth::check_bearer(req, &self.config.admin_tokens) {
+            return res;
+        }
+        let downloads = self.downloads.list();
+        Response::json(&downloads.iter().map(|v| DownloadInfo {
+            filename: &v.filename,
+            url: &v.url,
+            size: v.size,
+            received: v.buffer.len(),
+            readers: v.buffer.readers(),
+            started: v.started.to_string(),
+        }).collect::<Vec<_>>())
+    }
+}
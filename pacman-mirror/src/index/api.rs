@@ -0,0 +1,224 @@
+use std::{collections::BTreeMap, str::FromStr};
+
+use itertools::Itertools;
+use rouille::{Request, Response};
+use serde::Serialize;
+
+use crate::{cache::DataSource, database::{package::Package, repo::state::FetchType, rules::{Filtered, Verdict}, Repo}, date::DateTime, Index};
+
+
+const DEFAULT_LIMIT: usize = 100;
+const MAX_LIMIT: usize = 1000;
+
+#[derive(Serialize)]
+struct RepoInfo<'a> {
+    name: &'a str,
+    local: bool,
+    overlays: Vec<&'a str>,
+    mirrors: usize,
+    packages: usize,
+    ty: FetchType,
+    last_updated: Option<String>,
+    degraded: Option<String>,
+    excluded: Vec<FilteredInfo<'a>>,
+}
+
+#[derive(Serialize)]
+struct FilteredInfo<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<&'a str>,
+    rule: String,
+    verdict: &'static str,
+    /// The upstream version kept out.
+    version: &'a str,
+}
+
+#[derive(Serialize)]
+struct PackageInfo<'a> {
+    name: &'a str,
+    version: &'a str,
+    filename: &'a str,
+    csize: usize,
+    builddate: String,
+    mirrors: usize,
+    overlay: Option<&'a str>,
+    cache: &'static str,
+    rule: Option<FilteredInfo<'a>>,
+}
+
+#[derive(Serialize)]
+struct PackagePage<'a> {
+    total: usize,
+    offset: usize,
+    limit: usize,
+    packages: Vec<PackageInfo<'a>>,
+}
+
+#[derive(Serialize)]
+struct PackageDetail<'a> {
+    #[serde(flatten)]
+    info: PackageInfo<'a>,
+    sha256sum: String,
+    pgpsig: Option<&'a str>,
+    /// Every field of the package's `desc`, split into lines.
+    fields: BTreeMap<&'a str, Vec<&'a str>>,
+    files: Option<Vec<&'a str>>,
+    urls: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct MirrorInfo<'a> {
+    url: &'a str,
+    repos: Vec<MirrorRepo<'a>>,
+}
+
+#[derive(Serialize)]
+struct MirrorRepo<'a> {
+    repo: &'a str,
+    url: &'a str,
+    local: bool,
+    quarantined: bool,
+    packages: usize,
+}
+
+fn cache_name(src: &DataSource) -> &'static str {
+    match src {
+        DataSource::Empty => "none",
+        DataSource::Memory(_) => "memory",
+        DataSource::File(_) => "local",
+    }
+}
+
+fn filtered_info<'a>(index: &Index, name: Option<&'a str>, filtered: &'a Filtered) -> FilteredInfo<'a> {
+    FilteredInfo {
+        name,
+        rule: index.config.rules[filtered.rule].to_string(),
+        verdict: match filtered.verdict {
+            Verdict::Exclude => "exclude",
+            Verdict::Hold => "hold",
+        },
+        version: &filtered.version,
+    }
+}
+
+fn package_info<'a>(index: &Index, repo: &'a Repo, filtered: Option<&'a Filtered>, pkg: &'a Package) -> PackageInfo<'a> {
+    PackageInfo {
+        name: &pkg.desc.name,
+        version: &pkg.desc.version,
+        filename: &pkg.desc.filename,
+        csize: pkg.desc.csize,
+        builddate: DateTime::from_system(pkg.desc.builddate).to_string(),
+        mirrors: pkg.mirrors.len(),
+        overlay: pkg.mirrors.iter().find(|m| repo.is_overlay(m)).map(|m| m.repo_name.as_ref()),
+        cache: cache_name(&pkg.cache.get()),
+        rule: filtered.map(|v| filtered_info(index, None, v)),
+    }
+}
+
+fn param<T: FromStr>(req: &Request, name: &str) -> Result<Option<T>, Response> {
+    match req.get_param(name) {
+        None => Ok(None),
+        Some(src) => src.parse().map(Some)
+            .map_err(|_| Response::text(format!("Invalid value for {name}: {src:?}\n")).with_status_code(400)),
+    }
+}
+
+impl Index {
+    pub fn api_repos(&self) -> Response {
+        let repos = self.config.repo_names()
+            .filter_map(|name| self.db.repos.get(name))
+            .map(|repo| (repo, repo.state.read().unwrap()))
+            .collect_vec();
+        Response::json(&repos.iter().map(|(repo, state)| RepoInfo {
+            name: &repo.name,
+            local: repo.local_index().is_some(),
+            overlays: repo.overlays.iter().map(|v| v.repo_name.as_ref()).collect(),
+            mirrors: repo.mirrors.len(),
+            packages: state.packages.len(),
+            ty: state.ty,
+            last_updated: Some(state.last_updated)
+                .filter(|v| *v > std::time::SystemTime::UNIX_EPOCH)
+                .map(|v| DateTime::from_system(v).to_string()),
+            degraded: state.degraded.as_ref().map(|v| v.to_string()),
+            excluded: state.filtered.iter()
+                .filter(|(name, _)| !state.packages.contains_key(*name))
+                .sorted_by(|a, b| a.0.cmp(b.0))
+                .map(|(name, filtered)| filtered_info(self, Some(name), filtered))
+                .collect(),
+        }).collect_vec())
+    }
+    /// Served packages sorted by name, paged with `?offset=` and `?limit=`.
+    /// `?name=` keeps names containing it, `?cache=` (none, memory or local)
+    /// and `?mirror=` (a repo url) keep what's cached or mirrored there,
+    /// and `?held=true` keeps packages a rule applies to.
+    pub fn api_packages(&self, req: &Request, repo: String) -> Response {
+        let Some(repo) = self.db.repos.get(repo.as_str()) else {
+            return Response::empty_404();
+        };
+        let (offset, limit, held) = match (param(req, "offset"), param(req, "limit"), param(req, "held")) {
+            (Ok(offset), Ok(limit), Ok(held)) => (offset.unwrap_or(0), limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT), held.unwrap_or(false)),
+            (Err(res), _, _) | (_, Err(res), _) | (_, _, Err(res)) => return res,
+        };
+        let name = req.get_param("name");
+        let cache = req.get_param("cache");
+        let mirror = req.get_param("mirror");
+
+        let state = repo.state.read().unwrap();
+        let packages = state.packages.values()
+            .filter(|pkg| name.as_deref().is_none_or(|v| pkg.desc.name.contains(v)))
+            .filter(|pkg| cache.as_deref().is_none_or(|v| cache_name(&pkg.cache.get()) == v))
+            .filter(|pkg| mirror.as_deref().is_none_or(|v| pkg.mirrors.iter().any(|m| m.repo_url.as_ref() == v)))
+            .filter(|pkg| !held || state.filtered.contains_key(&pkg.desc.name))
+            .sorted_by(|a, b| a.desc.name.cmp(&b.desc.name))
+            .collect_vec();
+        Response::json(&PackagePage {
+            total: packages.len(),
+            offset,
+            limit,
+            packages: packages.into_iter()
+                .skip(offset)
+                .take(limit)
+                .map(|pkg| package_info(self, repo, state.filtered.get(&pkg.desc.name), pkg))
+                .collect(),
+        })
+    }
+    pub fn api_package(&self, repo: String, name: String) -> Response {
+        let Some(repo) = self.db.repos.get(repo.as_str()) else {
+            return Response::empty_404();
+        };
+        let state = repo.state.read().unwrap();
+        let Some(pkg) = state.packages.get(name.as_str()) else {
+            return Response::empty_404();
+        };
+        let desc = &pkg.desc;
+        Response::json(&PackageDetail {
+            info: package_info(self, repo, state.filtered.get(&desc.name), pkg),
+            sha256sum: hex::encode(desc.sha256sum),
+            pgpsig: desc.pgpsig.as_deref(),
+            fields: desc.fields().map(|(k, v)| (k, v.lines().collect())).collect(),
+            files: desc.files.as_deref().map(|v| v.lines().filter(|v| !v.is_empty() && *v != "%FILES%").collect()),
+            urls: pkg.mirrors.iter()
+                .map(|m| format!("{}/{}", m.repo_url.trim_end_matches('/'), desc.filename))
+                .collect(),
+        })
+    }
+    pub fn api_mirrors(&self) -> Response {
+        let mut mirrors = Vec::<MirrorInfo>::new();
+        for repo in self.config.repo_names().filter_map(|name| self.db.repos.get(name)) {
+            for mirror in repo.mirrors.iter() {
+                let entry = MirrorRepo {
+                    repo: &repo.name,
+                    url: &mirror.repo_url,
+                    local: mirror.local.is_some(),
+                    quarantined: mirror.is_quarantined(),
+                    packages: mirror.state.read().unwrap().packages.len(),
+                };
+                match mirrors.iter_mut().find(|v| v.url == mirror.mirror.url.as_ref()) {
+                    Some(v) => v.repos.push(entry),
+                    None => mirrors.push(MirrorInfo { url: &mirror.mirror.url, repos: vec![entry] }),
+                }
+            }
+        }
+        Response::json(&mirrors)
+    }
+}
@@ -0,0 +1,25 @@
+use rouille::{Request, Response};
+use sha2::{Digest, Sha256};
+
+use crate::config::Secret;
+
+
+/// Compares the digests of both values without stopping at the first
+/// difference, so response times don't reveal how much of a token matched.
+fn matches(token: &str, secret: &Secret) -> bool {
+    let (a, b) = (Sha256::digest(token), Sha256::digest(secret.expose()));
+    a.iter().zip(b.iter()).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+/// Checks the request carries one of `tokens` as a bearer token.
+pub fn check_bearer(req: &Request, tokens: &[Secret]) -> Result<(), Response> {
+    let token = req.header("Authorization").and_then(|v| v.strip_prefix("Bearer "));
+    match token {
+        _ if tokens.is_empty() => Err(Response::text("Not enabled\n").with_status_code(403)),
+        None => Err(Response::text("Missing bearer token\n")
+            .with_status_code(401)
+            .with_additional_header("WWW-Authenticate", "Bearer")),
+        Some(token) if tokens.iter().fold(false, |acc, v| acc | matches(token.trim(), v)) => Ok(()),
+        Some(_) => Err(Response::text("Invalid token\n").with_status_code(403)),
+    }
+}
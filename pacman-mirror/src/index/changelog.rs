@@ -0,0 +1,75 @@
+use maud::{html, Markup, PreEscaped};
+use rouille::{Request, Response};
+
+use crate::{database::repo::diff::Diff, date::DateTime, Index};
+
+use super::template;
+
+
+fn diff_table(diff: &Diff) -> Markup {
+    html! {
+        table {
+            tr {
+                th { "Name" }
+                th { "Change" }
+                th { "Old" }
+                th { "New" }
+            }
+            @for v in diff.added.iter() {
+                tr { td { (v.name) } td { "added" } td {} td { (v.version) } }
+            }
+            @for v in diff.removed.iter() {
+                tr { td { (v.name) } td { "removed" } td { (v.version) } td {} }
+            }
+            @for v in diff.upgraded.iter() {
+                tr { td { (v.name) } td { "upgraded" } td { (v.old) } td { (v.new) } }
+            }
+            @for v in diff.downgraded.iter() {
+                tr { td { (v.name) } td { "downgraded" } td { (v.old) } td { (v.new) } }
+            }
+        }
+    }
+}
+
+impl Index {
+    pub fn get_changelog(&self, req: &Request, repo: String) -> Response {
+        let Some(repo) = self.db.repos.get(repo.as_str()) else {
+            return Response::empty_404();
+        };
+        Response::html(template(req.raw_url(), html! {
+            p { a href="feed.atom" { "Atom feed" } }
+            @for entry in repo.changes() {
+                h2 id=(entry.created().id()) { (entry.created()) ": " (entry.diff.summary()) }
+                (diff_table(&entry.diff))
+            }
+        }))
+    }
+    pub fn get_feed(&self, req: &Request, repo: String) -> Response {
+        let Some(repo) = self.db.repos.get(repo.as_str()) else {
+            return Response::empty_404();
+        };
+        let base = format!("http://{}/{}/", req.header("Host").unwrap_or(&self.config.listen), repo.name);
+        let changes = repo.changes();
+        let updated = changes.first().map(|v| v.created()).unwrap_or(DateTime::from_secs(0));
+        let feed = html! {
+            (PreEscaped(r#"<?xml version="1.0" encoding="utf-8"?>"#))
+            feed xmlns="http://www.w3.org/2005/Atom" {
+                id { (base) "changes" }
+                title { (self.config.name) "/" (repo.name) " changes" }
+                updated { (updated) }
+                link rel="self" href={ (base) "feed.atom" } {}
+                link rel="alternate" href={ (base) "changes" } {}
+                @for entry in changes {
+                    entry {
+                        id { (base) "changes#" (entry.created().id()) }
+                        title { (repo.name) ": " (entry.diff.summary()) }
+                        updated { (entry.created()) }
+                        author { name { (self.config.name) } }
+                        content type="html" { (diff_table(&entry.diff).into_string()) }
+                    }
+                }
+            }
+        };
+        Response::from_data("application/atom+xml", feed.into_string())
+    }
+}
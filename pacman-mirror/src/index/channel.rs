@@ -0,0 +1,96 @@
+use std::sync::Arc;
+
+use maud::html;
+use rouille::{Request, Response};
+
+use crate::{database::{channel::{Channel, ChannelError, Target}, snapshot::Snapshot}, Index};
+
+use super::{auth, snapshot::snapshot_package_list, template};
+
+
+fn channel_response(res: Result<Channel, ChannelError>) -> anyhow::Result<Response> {
+    Ok(match res {
+        Ok(channel) => Response::json(&channel),
+        Err(err@(ChannelError::UnknownChannel(_) | ChannelError::UnknownRepo(_))) => Response::text(format!("{err}\n")).with_status_code(404),
+        Err(ChannelError::Other(err)) => return Err(err),
+        Err(err) => Response::text(format!("{err}\n")).with_status_code(409),
+    })
+}
+
+impl Index {
+    pub fn is_channel(&self, name: &str) -> bool {
+        self.db.channels.contains(name)
+    }
+    fn channel_snapshot(&self, channel: &str, repo: &str) -> anyhow::Result<Option<Arc<Snapshot>>> {
+        let (Some(channel), Some(repo)) = (self.db.channels.get(channel), self.db.repos.get(repo)) else {
+            return Ok(None);
+        };
+        match channel.pins.get(&repo.name) {
+            Some(id) => self.db.snapshots.get(repo, id),
+            None => Ok(None),
+        }
+    }
+    pub fn get_channel(&self, req: &Request, name: String) -> Response {
+        let channel = self.db.channels.get(&name).unwrap_or_default();
+        Response::html(template(req.raw_url(), html! {
+            table {
+                tr {
+                    th { "Repo" }
+                    th { "Snapshot" }
+                }
+                @for repo in self.config.repo_names() {
+                    tr {
+                        @match channel.pins.get(repo) {
+                            Some(id) => {
+                                td { a href={ (repo) "/" } { (repo) } }
+                                td { (id) }
+                            }
+                            None => {
+                                td { (repo) }
+                                td { "not promoted yet" }
+                            }
+                        }
+                    }
+                }
+            }
+        }))
+    }
+    pub fn get_channel_package_list(&self, req: &Request, channel: String, repo: String) -> anyhow::Result<Response> {
+        Ok(match self.channel_snapshot(&channel, &repo)? {
+            Some(snapshot) => snapshot_package_list(req, &snapshot),
+            None => Response::empty_404(),
+        })
+    }
+    pub fn get_channel_item(&self, channel: String, repo: String, file: Arc<str>) -> anyhow::Result<Response> {
+        match self.channel_snapshot(&channel, &repo)? {
+            Some(snapshot) => self.serve_snapshot_item(snapshot, &repo, file),
+            None => Ok(Response::empty_404()),
+        }
+    }
+
+    pub fn admin_channels(&self, req: &Request) -> Response {
+        if let Err(res) = auth::check_bearer(req, &self.config.admin_tokens) {
+            return res;
+        }
+        Response::json(&self.db.channels.all())
+    }
+    /// Pins a channel to `?snapshot=` (an id or a date), or to what's served
+    /// now if it's missing. `?repo=` limits the change to one repo.
+    pub fn promote_channel(&self, req: &Request, name: String) -> anyhow::Result<Response> {
+        if let Err(res) = auth::check_bearer(req, &self.config.admin_tokens) {
+            return Ok(res);
+        }
+        let target = match req.get_param("snapshot") {
+            Some(src) => Target::Snapshot(src.into()),
+            None => Target::Current,
+        };
+        let repo = req.get_param("repo");
+        channel_response(self.db.channels.promote(&self.db.repos, &name, repo.as_deref(), &target))
+    }
+    pub fn rollback_channel(&self, req: &Request, name: String) -> anyhow::Result<Response> {
+        if let Err(res) = auth::check_bearer(req, &self.config.admin_tokens) {
+            return Ok(res);
+        }
+        channel_response(self.db.channels.rollback(&name))
+    }
+}
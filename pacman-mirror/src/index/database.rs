@@ -1,82 +1,48 @@
-use std::{io::Cursor, path::PathBuf, sync::{mpsc, Arc}, time::SystemTime};
-use log::{error, warn};
+use std::{sync::Arc, time::SystemTime};
+use log::error;
 use rouille::{Response, ResponseBody};
-use tar::{EntryType, Header};
 
-use crate::{database::{repo::state::FetchType, Repo}, Index};
+use crate::{database::{archive, repo::state::FetchType, Repo}, Index};
 
-impl Index {
-    fn send_database(&self, writer: os_pipe::PipeWriter, repo: Arc<Repo>, ty: FetchType) -> anyhow::Result<()> {
-        let mut tar_builder = tar::Builder::new(flate2::write::GzEncoder::new(writer, flate2::Compression::new(1)));
+/// Streams a database built by `fill` on a separate thread.
+pub fn database_response(ty: FetchType, fill: impl FnOnce(&mut archive::Writer<os_pipe::PipeWriter>) -> anyhow::Result<()> + Send + 'static) -> anyhow::Result<Response> {
+    let (reader, writer) = os_pipe::pipe()?;
 
-        if repo.should_refresh(ty) {
-            let (tx, rx) = mpsc::channel::<()>();
-            std::thread::spawn({
-                let repo = repo.clone();
-                move || repo.try_refresh(Some(tx), ty)
-            });
-            // wait for the signal (its result doesn't matter)
-            _ = rx.recv();
+    std::thread::spawn(move || {
+        let mut writer = archive::Writer::new(writer, ty, 1);
+        if let Err(err) = fill(&mut writer).and_then(|_| writer.finish().map(drop)) {
+            error!("{err:?}");
         }
-        repo.get_from_mirrors(|desc| {
-            let path = PathBuf::from(format!("{}-{}", desc.name.as_ref(), desc.version.as_ref()));
-            let now = SystemTime::UNIX_EPOCH.elapsed().map(|v| v.as_secs()).unwrap_or(0);
-    
-            let send_file = |builder: &mut tar::Builder<_>, name: &str, bytes: &[u8]| -> anyhow::Result<()> {
-                builder.append(&{
-                    let mut v = Header::new_gnu();
-                    v.set_path(path.join(name))?;
-                    v.set_entry_type(EntryType::file());
-                    v.set_size(bytes.len().try_into()?);
-                    v.set_mode(0o644);
-                    v.set_mtime(now);
-                    v.set_cksum();
-                    v
-                }, Cursor::new(bytes))?;
-                Ok(())
-            };
-            tar_builder.append(&{
-                let mut v = Header::new_gnu();
-                v.set_path(&path)?;
-                v.set_entry_type(EntryType::dir());
-                v.set_mode(0o755);
-                v.set_mtime(now);
-                v.set_cksum();
-                v
-            }, std::io::empty())?;
-            
-            send_file(&mut tar_builder, "desc", &desc.write_to_vec()?)?;
+    });
 
-            if ty > FetchType::Db {
-                if let Some(files) = &desc.files {
-                    send_file(&mut tar_builder, "files", files.as_bytes())?;
-                } else {
-                    warn!("Package {} is missing file info", desc.name);
-                }
-            }
-    
-            Ok(())
-        })
-    }
-    pub fn get_database(self: &Arc<Self>, repo: Arc<Repo>, ty: FetchType) -> anyhow::Result<Response> {
-        let (reader, writer) = os_pipe::pipe()?;
-        let db = self.clone();
+    Ok(Response {
+        status_code: 200,
+        headers: vec![
+            ("Content-Type".into(), "application/x-tar".into()),
+            ("Content-Encoding".into(), "x-gzip".into()),
+        ],
+        data: ResponseBody::from_reader(Box::new(reader)),
+        upgrade: None,
+    })
+}
 
-        std::thread::spawn(move || {
-            if let Err(err) = db.send_database(writer, repo, ty) {
-                error!("{err:?}");
+impl Index {
+    pub fn get_database(self: &Arc<Self>, repo: Arc<Repo>, ty: FetchType) -> anyhow::Result<Response> {
+        database_response(ty, move |writer| {
+            if repo.should_refresh(ty) {
+                repo.try_refresh(ty);
+            }
+            // there's nothing to serve before the first refresh, which another request may be running
+            if repo.state.read().unwrap().last_updated == SystemTime::UNIX_EPOCH {
+                repo.wait_for_update();
             }
-        });
-    
-        Ok(Response {
-            status_code: 200,
-            headers: vec![
-                ("Content-Type".into(), "application/x-tar".into()),
-                ("Content-Encoding".into(), "x-gzip".into()),
-            ],
-            data: ResponseBody::from_reader(Box::new(reader)),
-            upgrade: None,
+            // only what passed the thresholds is served, never a list still being fetched
+            let mut packages = Vec::from_iter(repo.state.read().unwrap().packages.values().map(|v| v.desc.clone()));
+            packages.sort_by(|a, b| a.name.cmp(&b.name));
+            for desc in packages {
+                writer.append(&desc)?;
+            }
+            Ok(())
         })
     }
 }
-
@@ -0,0 +1,141 @@
+use std::sync::Arc;
+
+use maud::html;
+use rouille::{Request, Response};
+use serde::Serialize;
+
+use crate::{database::{desc::Desc, search::files::FileQuery}, Index};
+
+use super::template;
+
+
+const DEFAULT_LIMIT: usize = 100;
+const MAX_LIMIT: usize = 10000;
+
+#[derive(Serialize)]
+struct FileResult<'a> {
+    repo: &'a str,
+    package: &'a str,
+    version: &'a str,
+    path: &'a str,
+}
+
+#[derive(Serialize)]
+struct FileResults<'a> {
+    total: usize,
+    results: Vec<FileResult<'a>>,
+    /// Repos that couldn't be searched because they have no file lists loaded.
+    missing: Vec<&'a str>,
+}
+
+struct Files {
+    limit: usize,
+    hits: Vec<(Arc<str>, Arc<Desc>, String)>,
+    missing: Vec<Arc<str>>,
+}
+
+impl Index {
+    /// Packages owning `?path=`, matched with `?mode=` (exact, glob or regex), or the
+    /// files of the package `?package=`. Both are limited to `?repo=` if it's given.
+    fn files(&self, req: &Request) -> Result<Option<Files>, Response> {
+        let bad_request = |err: String| Response::text(format!("{err}\n")).with_status_code(400);
+        let limit = match req.get_param("limit") {
+            Some(src) => src.parse::<usize>().map_err(|_| bad_request(format!("Invalid value for limit: {src:?}")))?.min(MAX_LIMIT),
+            None => DEFAULT_LIMIT,
+        };
+        let repo = req.get_param("repo").filter(|v| !v.is_empty());
+        let repos = self.config.repo_names()
+            .filter(|v| repo.as_deref().is_none_or(|repo| repo == v.as_ref()))
+            .filter_map(|name| self.db.repos.get(name));
+
+        if let Some(package) = req.get_param("package").filter(|v| !v.is_empty()) {
+            let mut files = Files { limit, hits: Vec::new(), missing: Vec::new() };
+            for repo in repos {
+                let Some(desc) = repo.state.read().unwrap().packages.get(package.as_str()).map(|v| v.desc.clone()) else {
+                    continue;
+                };
+                let Some(list) = desc.files.clone() else {
+                    files.missing.push(repo.name.clone());
+                    continue;
+                };
+                files.hits.extend(list.lines()
+                    .filter(|v| !v.is_empty() && !v.starts_with('%'))
+                    .map(|path| (repo.name.clone(), desc.clone(), format!("/{path}"))));
+            }
+            return Ok(Some(files));
+        }
+
+        let Some(path) = req.get_param("path").filter(|v| !v.trim().is_empty()) else {
+            return Ok(None);
+        };
+        let mode = req.get_param("mode").filter(|v| !v.is_empty());
+        let query = FileQuery::new(mode.as_deref().unwrap_or("exact"), &path).map_err(|err| bad_request(format!("{err}")))?;
+        let mut files = Files { limit, hits: Vec::new(), missing: Vec::new() };
+        for (repo, hits) in self.db.search_files(&query, repo.as_deref()) {
+            match hits {
+                Some(hits) => files.hits.extend(hits.into_iter().map(|(desc, path)| (repo.clone(), desc, path))),
+                None => files.missing.push(repo),
+            }
+        }
+        Ok(Some(files))
+    }
+    pub fn api_files(&self, req: &Request) -> Response {
+        let files = match self.files(req) {
+            Ok(Some(files)) => files,
+            Ok(None) => return Response::text("Missing path or package\n").with_status_code(400),
+            Err(res) => return res,
+        };
+        Response::json(&FileResults {
+            total: files.hits.len(),
+            results: files.hits.iter().take(files.limit).map(|(repo, desc, path)| FileResult {
+                repo,
+                package: &desc.name,
+                version: &desc.version,
+                path,
+            }).collect(),
+            missing: files.missing.iter().map(|v| v.as_ref()).collect(),
+        })
+    }
+    pub fn get_files(&self, req: &Request) -> Response {
+        let files = match self.files(req) {
+            Ok(files) => files,
+            Err(res) => return res,
+        };
+        let mode = req.get_param("mode").unwrap_or_default();
+        Response::html(template(req.raw_url(), html! {
+            form action="/files" {
+                input type="search" name="path" placeholder="/usr/bin/foo" value=(req.get_param("path").unwrap_or_default());
+                " "
+                select name="mode" {
+                    @for value in ["exact", "glob", "regex"] {
+                        option value=(value) selected[value == mode] { (value) }
+                    }
+                }
+                " or package "
+                input type="search" name="package" value=(req.get_param("package").unwrap_or_default());
+                " "
+                input type="submit" value="Search";
+            }
+            @if let Some(files) = &files {
+                @if !files.missing.is_empty() {
+                    p.warning { "No file lists are loaded for: " (files.missing.join(", ")) }
+                }
+                p { (files.hits.len()) " results" }
+                table {
+                    tr {
+                        th { "Repo" }
+                        th { "Package" }
+                        th { "Path" }
+                    }
+                    @for (repo, desc, path) in files.hits.iter().take(files.limit) {
+                        tr {
+                            td { (repo) }
+                            td { a href={ "/" (repo) "/package/" (desc.name) } { (desc.name) " " (desc.version) } }
+                            td { (path) }
+                        }
+                    }
+                }
+            }
+        }))
+    }
+}
@@ -0,0 +1,124 @@
+use std::{io::Write, process::{Command, Stdio}, str::FromStr};
+
+use anyhow::Context;
+use log::error;
+use rouille::{Request, Response};
+use serde::Serialize;
+
+use crate::{database::graph::{DepGraph, Kind}, Index};
+
+
+const DEFAULT_DEPTH: usize = 2;
+const MAX_DEPTH: usize = 16;
+
+#[derive(Serialize)]
+struct DepInfo<'a> {
+    kind: Kind,
+    dep: &'a str,
+    #[serde(flatten)]
+    package: Option<PackageRef<'a>>,
+}
+
+#[derive(Serialize)]
+struct PackageRef<'a> {
+    repo: &'a str,
+    name: &'a str,
+    version: &'a str,
+}
+
+#[derive(Serialize)]
+struct Reached<'a> {
+    #[serde(flatten)]
+    package: PackageRef<'a>,
+    depth: usize,
+}
+
+fn package_ref(graph: &DepGraph, id: usize) -> PackageRef<'_> {
+    let node = &graph.nodes[id];
+    PackageRef { repo: &node.repo, name: &node.desc.name, version: &node.desc.version }
+}
+
+fn param<T: FromStr>(req: &Request, name: &str, default: T) -> Result<T, Response> {
+    match req.get_param(name) {
+        None => Ok(default),
+        Some(src) => src.parse()
+            .map_err(|_| Response::text(format!("Invalid value for {name}: {src:?}\n")).with_status_code(400)),
+    }
+}
+
+/// Renders `dot` with Graphviz, or `None` if it isn't installed.
+fn svg(dot: String) -> anyhow::Result<Option<Vec<u8>>> {
+    let mut child = match Command::new("dot").arg("-Tsvg").stdin(Stdio::piped()).stdout(Stdio::piped()).spawn() {
+        Ok(child) => child,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    // written aside, dot may fill its stdout before reading all of its input
+    let mut stdin = child.stdin.take().context("dot has no stdin")?;
+    let writer = std::thread::spawn(move || stdin.write_all(dot.as_bytes()));
+    let output = child.wait_with_output()?;
+    let written = writer.join().map_err(|_| anyhow::anyhow!("Writing to dot panicked"))?;
+    if !output.status.success() {
+        anyhow::bail!("dot exited with {}", output.status);
+    }
+    written?;
+    Ok(Some(output.stdout))
+}
+
+impl Index {
+    /// What a package depends on, or with `reverse` what depends on it. With
+    /// `?transitive=true` it follows `depends` up to `?depth=` levels instead.
+    pub fn api_dependencies(&self, req: &Request, repo: String, name: String, reverse: bool) -> Response {
+        let graph = self.db.graph();
+        let Some(root) = graph.find(&repo, &name) else {
+            return Response::empty_404();
+        };
+        let (transitive, depth) = match (param(req, "transitive", false), param(req, "depth", MAX_DEPTH)) {
+            (Ok(transitive), Ok(depth)) => (transitive, depth.min(MAX_DEPTH)),
+            (Err(res), _) | (_, Err(res)) => return res,
+        };
+        if transitive {
+            return Response::json(&graph.walk(root, reverse, depth).into_iter()
+                .skip(1)
+                .map(|(id, depth)| Reached { package: package_ref(&graph, id), depth })
+                .collect::<Vec<_>>());
+        }
+        let deps = match reverse {
+            false => graph.forward[root].iter()
+                .map(|edge| DepInfo { kind: edge.kind, dep: &edge.dep, package: edge.to.map(|id| package_ref(&graph, id)) })
+                .collect::<Vec<_>>(),
+            true => graph.reverse[root].iter()
+                .map(|&(from, idx)| {
+                    let edge = &graph.forward[from][idx];
+                    DepInfo { kind: edge.kind, dep: &edge.dep, package: Some(package_ref(&graph, from)) }
+                })
+                .collect(),
+        };
+        Response::json(&deps)
+    }
+    /// The `depends` around a package as `?format=dot` or `svg`, `?depth=` levels
+    /// out, following what depends on it instead with `?reverse=true`.
+    pub fn get_dependency_graph(&self, req: &Request, repo: String, name: String) -> Response {
+        let graph = self.db.graph();
+        let Some(root) = graph.find(&repo, &name) else {
+            return Response::empty_404();
+        };
+        let (reverse, depth) = match (param(req, "reverse", false), param(req, "depth", DEFAULT_DEPTH)) {
+            (Ok(reverse), Ok(depth)) => (reverse, depth.min(MAX_DEPTH)),
+            (Err(res), _) | (_, Err(res)) => return res,
+        };
+        let dot = graph.dot(root, reverse, depth);
+        match req.get_param("format").as_deref() {
+            None | Some("dot") => Response::from_data("text/vnd.graphviz; charset=utf-8", dot),
+            Some("svg") => match svg(dot) {
+                Ok(Some(svg)) => Response::from_data("image/svg+xml", svg),
+                Ok(None) => Response::text("SVG output needs Graphviz's dot to be installed\n").with_status_code(501),
+                Err(err) => {
+                    error!("Failed to render the graph of {repo}/{name}: {err:?}");
+                    Response::text("Failed to render the graph\n").with_status_code(500)
+                }
+            },
+            Some(format) => Response::text(format!("Unknown format {format:?}, expected dot or svg\n")).with_status_code(400),
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use std::{collections::HashSet, sync::Arc, time::SystemTime};
+
+use rouille::Response;
+use serde::Serialize;
+
+use crate::{database::{repo::state::{Degraded, FetchType}, Repo}, date::DateTime, Index};
+
+
+#[derive(Serialize)]
+struct RepoHealth<'a> {
+    repo: &'a str,
+    ready: bool,
+    packages: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_updated: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    degraded: Option<String>,
+    /// Mirrors that aren't quarantined and serve packages of the last accepted refresh.
+    healthy_mirrors: usize,
+    mirrors: usize,
+}
+
+#[derive(Serialize)]
+struct Alive {
+    status: &'static str,
+}
+
+#[derive(Serialize)]
+struct Health<'a> {
+    ready: bool,
+    repos: Vec<RepoHealth<'a>>,
+}
+
+fn repo_health(repo: &Repo) -> RepoHealth<'_> {
+    let state = repo.state.read().unwrap();
+    let healthy_mirrors = match state.degraded {
+        Some(Degraded::Offline) => 0,
+        _ => {
+            let serving = state.packages.values()
+                .flat_map(|pkg| pkg.mirrors.iter())
+                .map(Arc::as_ptr)
+                .collect::<HashSet<_>>();
+            repo.mirrors.iter()
+                .filter(|mirror| !mirror.is_quarantined() && serving.contains(&Arc::as_ptr(mirror)))
+                .count()
+        }
+    };
+    RepoHealth {
+        repo: &repo.name,
+        ready: !state.packages.is_empty() && healthy_mirrors > 0,
+        packages: state.packages.len(),
+        last_updated: (state.last_updated > SystemTime::UNIX_EPOCH).then(|| DateTime::from_system(state.last_updated).to_string()),
+        degraded: state.degraded.as_ref().map(|v| v.to_string()),
+        healthy_mirrors,
+        mirrors: repo.mirrors.len(),
+    }
+}
+
+impl Index {
+    fn health(&self) -> Health<'_> {
+        let repos = self.config.repo_names()
+            .filter_map(|name| self.db.repos.get(name))
+            .map(|repo| repo_health(repo))
+            .collect::<Vec<_>>();
+        Health { ready: repos.iter().all(|v| v.ready), repos }
+    }
+    /// Answers as long as the process can handle requests, whatever the repos are doing.
+    pub fn get_healthz(&self) -> Response {
+        Response::json(&Alive { status: "ok" })
+    }
+    /// Responds with 503 until every repo serves packages from at least one
+    /// healthy mirror. Repos that haven't been refreshed yet are refreshed in
+    /// the background, so an instance kept out of rotation still becomes ready.
+    pub fn get_readyz(&self) -> Response {
+        for repo in self.config.repo_names().filter_map(|name| self.db.repos.get(name)) {
+            if repo.state.read().unwrap().packages.is_empty() && repo.should_refresh(FetchType::Db) {
+                let repo = repo.clone();
+                std::thread::spawn(move || repo.try_refresh(FetchType::Db));
+            }
+        }
+        let health = self.health();
+        let status = if health.ready { 200 } else { 503 };
+        Response::json(&health).with_status_code(status)
+    }
+}
@@ -0,0 +1,81 @@
+use rouille::Response;
+
+use crate::{cache::DataSource, metrics::Writer, Index};
+
+
+impl Index {
+    /// Counters in the Prometheus text format.
+    pub fn get_metrics(&self) -> Response {
+        let repos = self.config.repo_names()
+            .filter_map(|name| self.db.repos.get(name))
+            .collect::<Vec<_>>();
+        let mut dst = Writer::default();
+
+        dst.family("pacman_mirror_cache_hits_total", "counter", "Packages served from the cache.");
+        for repo in repos.iter() {
+            dst.counter("pacman_mirror_cache_hits_total", &[("repo", &repo.name)], &repo.metrics.hits);
+        }
+        dst.family("pacman_mirror_cache_misses_total", "counter", "Packages fetched from a mirror to be served.");
+        for repo in repos.iter() {
+            dst.counter("pacman_mirror_cache_misses_total", &[("repo", &repo.name)], &repo.metrics.misses);
+        }
+        dst.family("pacman_mirror_served_bytes_total", "counter", "Bytes of packages served to clients from the cache, or while they were downloaded from a mirror.");
+        for repo in repos.iter() {
+            dst.counter("pacman_mirror_served_bytes_total", &[("repo", &repo.name), ("source", "cache")], &repo.metrics.cached_bytes);
+            dst.counter("pacman_mirror_served_bytes_total", &[("repo", &repo.name), ("source", "upstream")], &repo.metrics.upstream_bytes);
+        }
+
+        dst.family("pacman_mirror_downloads_in_flight", "gauge", "Packages being downloaded from mirrors.");
+        dst.sample("pacman_mirror_downloads_in_flight", &[], self.downloads.list().len() as f64);
+
+        dst.family("pacman_mirror_mirror_requests_total", "counter", "Requests sent to each mirror.");
+        for repo in repos.iter() {
+            for mirror in repo.mirrors.iter() {
+                dst.counter("pacman_mirror_mirror_requests_total", &[("mirror", mirror.mirror.url()), ("repo", &repo.name)], &mirror.metrics.requests);
+            }
+        }
+        dst.family("pacman_mirror_mirror_errors_total", "counter", "Requests to each mirror that failed.");
+        for repo in repos.iter() {
+            for mirror in repo.mirrors.iter() {
+                dst.counter("pacman_mirror_mirror_errors_total", &[("mirror", mirror.mirror.url()), ("repo", &repo.name)], &mirror.metrics.errors);
+            }
+        }
+        dst.family("pacman_mirror_mirror_downloaded_bytes_total", "counter", "Bytes of packages downloaded from each mirror, including prefetching.");
+        for repo in repos.iter() {
+            for mirror in repo.mirrors.iter() {
+                dst.counter("pacman_mirror_mirror_downloaded_bytes_total", &[("mirror", mirror.mirror.url()), ("repo", &repo.name)], &mirror.metrics.bytes);
+            }
+        }
+        dst.family("pacman_mirror_mirror_request_duration_seconds", "histogram", "Time until each mirror responded.");
+        for repo in repos.iter() {
+            for mirror in repo.mirrors.iter() {
+                dst.histogram("pacman_mirror_mirror_request_duration_seconds", &[("mirror", mirror.mirror.url()), ("repo", &repo.name)], &mirror.metrics.latency);
+            }
+        }
+
+        dst.family("pacman_mirror_refresh_duration_seconds", "histogram", "Time taken by each refresh.");
+        for repo in repos.iter() {
+            dst.histogram("pacman_mirror_refresh_duration_seconds", &[("repo", &repo.name)], &repo.metrics.refresh_duration);
+        }
+        dst.family("pacman_mirror_packages", "gauge", "Packages served by each repo.");
+        for repo in repos.iter() {
+            dst.sample("pacman_mirror_packages", &[("repo", &repo.name)], repo.state.read().unwrap().packages.len() as f64);
+        }
+
+        dst.family("pacman_mirror_cache_size_bytes", "gauge", "Size of the packages cached in memory or found on disk.");
+        for repo in repos.iter() {
+            let (mut memory, mut disk) = (0, 0);
+            for package in repo.state.read().unwrap().packages.values() {
+                match package.cache.get() {
+                    DataSource::Empty => (),
+                    DataSource::Memory(buffer) => memory += buffer.len(),
+                    DataSource::File(_) => disk += package.desc.csize,
+                }
+            }
+            dst.sample("pacman_mirror_cache_size_bytes", &[("repo", &repo.name), ("kind", "memory")], memory as f64);
+            dst.sample("pacman_mirror_cache_size_bytes", &[("repo", &repo.name), ("kind", "disk")], disk as f64);
+        }
+
+        Response::from_data("text/plain; version=0.0.4; charset=utf-8", dst.finish())
+    }
+}
@@ -1,17 +1,16 @@
-use std::{io::{Read, Write}, path::Path, sync::Arc};
-use anyhow::{anyhow, bail};
+use std::{fs::File, io::{Read, Write}, path::Path, sync::Arc};
+use anyhow::bail;
 use log::{debug, error, info, warn};
 use rand::seq::SliceRandom;
 use replay_buffer::ReplayBufferWriter;
 use rouille::{Response, ResponseBody};
 use sha2::Digest;
 
-use crate::{cache::DataSource, database::Repo, Index};
+use crate::{cache::{DataSource, Download}, database::{package::Package, prefetch::Prefetcher, Repo}, date::DateTime, metrics::{self, MirrorMetrics}, Index};
 
 
-pub fn download_package(repo: Arc<Repo>, name: Arc<str>, mut src: impl Read, mut dst: ReplayBufferWriter<u8>) -> anyhow::Result<()> {
-    let repo_state = repo.state.read().unwrap();
-    let package = repo_state.packages.get(name.as_ref()).ok_or_else(|| anyhow!("Package {name} is None"))?;
+/// Reads no faster than `throttle` allows, if the download is for prefetching.
+pub fn download_package(package: Package, mut src: impl Read, mut dst: ReplayBufferWriter<u8>, metrics: &MirrorMetrics, throttle: Option<&Prefetcher>) -> anyhow::Result<()> {
     let mut hasher = sha2::Sha256::new();
 
     package.cache.set(DataSource::Memory(dst.source().clone()));
@@ -19,7 +18,11 @@ pub fn download_package(repo: Arc<Repo>, name: Arc<str>, mut src: impl Read, mut
     let mut do_transfer = || -> std::io::Result<()> {
         let mut buffer = [0u8; 16384];
         while let len@1.. = src.read(&mut buffer)? {
+            if let Some(throttle) = throttle {
+                throttle.throttle(len);
+            }
             let buf = &buffer[..len];
+            metrics::inc(&metrics.bytes, len as u64);
             hasher.write_all(buf)?;
             hasher.flush()?;
             dst.write_all(buf)?;
@@ -29,18 +32,57 @@ pub fn download_package(repo: Arc<Repo>, name: Arc<str>, mut src: impl Read, mut
     };
     if let Err(err) = do_transfer() {
         package.cache.set(DataSource::Empty);
+        metrics::inc(&metrics.errors, 1);
         return Err(err.into());
     }
     let digest = hasher.finalize();
     if digest.as_slice() != package.desc.sha256sum {
         package.cache.set(DataSource::Empty);
+        metrics::inc(&metrics.errors, 1);
         bail!("Checksums do not match");
     }
-    debug!("done transferring file: {} ({})", name, hex::encode(digest.as_slice()));
+    debug!("done transferring file: {} ({})", package.desc.filename, hex::encode(digest.as_slice()));
 
     Ok(())
 }
 
+/// Points the cache of `package` at a mirror's local copy of `file`, if it has one.
+fn use_local_copy(package: &Package, file: &str) {
+    if let DataSource::Empty = package.cache.get() {
+        let local = package.mirrors.iter()
+            .filter_map(|mirror| mirror.local_path())
+            .map(|dir| dir.join(file))
+            .find(|path| path.is_file());
+        if let Some(path) = local {
+            package.cache.set(DataSource::File(path.into()));
+        }
+    }
+}
+
+/// Who a package is served to.
+pub enum Requester {
+    /// A client of a repo, counted in its metrics.
+    Client(Arc<Repo>),
+    /// The prefetcher, which downloads within its rate.
+    Prefetch(Arc<Prefetcher>),
+}
+
+/// Counts the bytes a client reads in the metrics of its repo.
+struct Counted<R> {
+    inner: R,
+    repo: Arc<Repo>,
+    cached: bool,
+}
+
+impl<R: Read> Read for Counted<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        let metrics = &self.repo.metrics;
+        metrics::inc(if self.cached { &metrics.cached_bytes } else { &metrics.upstream_bytes }, len as u64);
+        Ok(len)
+    }
+}
+
 impl Index {
     pub fn get_package(&self, repo: Arc<Repo>, file: Arc<str>) -> anyhow::Result<Response> {
         let repo_state = repo.state.read().unwrap();
@@ -48,48 +90,83 @@ impl Index {
         let Some(package) = package_name.and_then(|v| repo_state.packages.get(v.as_ref())) else {
             return Ok(Response::empty_404());
         };
-        let response_body = match package.cache.get() {
+        let package = package.clone();
+        drop(repo_state);
+        self.serve_package(package, file, Requester::Client(repo))
+    }
+    /// Serves `package`, downloading it if it isn't cached.
+    pub fn serve_package(&self, package: Package, file: Arc<str>, requester: Requester) -> anyhow::Result<Response> {
+        use_local_copy(&package, &file);
+        let throttle = match &requester {
+            Requester::Prefetch(prefetch) => Some(prefetch.clone()),
+            Requester::Client(_) => None,
+        };
+        let source = package.cache.get();
+        let cached = !matches!(source, DataSource::Empty);
+        let reader: Box<dyn Read + Send> = match source {
             DataSource::Empty => {
                 let mut mirrors = package.mirrors.clone();
-                let cache = ReplayBufferWriter::new();
-                let reader = cache.source().read();
                 mirrors.shuffle(&mut rand::rng());
-    
-                for mirror in mirrors {
-                    let url = Path::new(mirror.as_ref()).join(file.as_ref());
-                    let url_str = url.to_string_lossy();
-                    let Ok(res) = minreq::get(&*url_str).send_lazy() else {
-                        warn!("{url_str} failed");
-                        continue;
-                    };
-                    if res.status_code != 200 {
-                        warn!("{url_str} got {} {}", res.status_code, res.reason_phrase);
-                        continue;
-                    }
-                    let (name, repo, cache) = (package.desc.name.clone(), repo.clone(), cache);
-                    std::thread::spawn(move || {
-                        info!("Started download: {}", url.to_string_lossy());
-                        if let Err(err) = download_package(repo, name, res, cache) {
-                            error!("{err}");
-                            return;
+
+                let res = mirrors.iter()
+                    .filter(|mirror| mirror.local_path().is_none() && !mirror.is_quarantined())
+                    .find_map(|mirror| {
+                        let url = Path::new(mirror.repo_url.as_ref()).join(file.as_ref());
+                        match mirror.get(&url.to_string_lossy()) {
+                            Ok(res) => Some((mirror.clone(), url, res)),
+                            Err(err) => {
+                                warn!("{} failed: {err}", url.to_string_lossy());
+                                None
+                            }
                         }
-                        info!("Download complete: {}", url.to_string_lossy());
                     });
-                    break;
-                }
-                ResponseBody::from_reader_and_size(reader, package.desc.csize)
+                let Some((mirror, url, res)) = res else {
+                    return Ok(Response::text("No mirror could provide this file\n").with_status_code(503));
+                };
+                let cache = ReplayBufferWriter::new();
+                let reader = cache.source().read();
+                let url = url.to_string_lossy();
+                self.downloads.add(Download {
+                    filename: file.clone(),
+                    url: url.as_ref().into(),
+                    size: package.desc.csize,
+                    started: DateTime::now(),
+                    buffer: cache.source().clone(),
+                });
+                std::thread::spawn({
+                    let package = package.clone();
+                    let downloads = self.downloads.clone();
+                    let url = url.into_owned();
+                    move || {
+                        info!("Started download: {url}");
+                        let buffer = cache.source().clone();
+                        let result = download_package(package, res, cache, &mirror.metrics, throttle.as_deref());
+                        downloads.remove(&buffer);
+                        match result {
+                            Ok(()) => info!("Download complete: {url}"),
+                            Err(err) => error!("{err}"),
+                        }
+                    }
+                });
+                Box::new(reader)
             }
-            DataSource::Memory(source) => {
-                let reader = source.read();
-                ResponseBody::from_reader_and_size(reader, package.desc.csize)
+            DataSource::Memory(source) => Box::new(source.read()),
+            DataSource::File(path) => Box::new(File::open(&path).inspect_err(|_| package.cache.set(DataSource::Empty))?),
+        };
+        let reader: Box<dyn Read + Send> = match requester {
+            Requester::Client(repo) => {
+                metrics::inc(if cached { &repo.metrics.hits } else { &repo.metrics.misses }, 1);
+                Box::new(Counted { inner: reader, repo, cached })
             }
+            Requester::Prefetch(_) => reader,
         };
+        let response_body = ResponseBody::from_reader_and_size(reader, package.desc.csize);
         Ok(Response {
             status_code: 200,
             headers: vec![
                 ("Content-Type".into(), "application/x-tar".into()),
             ],
-            data: response_body.with_chunked_threshold(usize::max_value()),
+            data: response_body.with_chunked_threshold(usize::MAX),
             upgrade: None,
         })
     }
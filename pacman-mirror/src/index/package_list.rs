@@ -2,7 +2,7 @@ use itertools::Itertools;
 use maud::html;
 use rouille::{Request, Response};
 
-use crate::{cache::DataSource, database::repo::state::FetchType, Index};
+use crate::{database::{repo::state::FetchType, rules::Verdict}, Index};
 
 use super::template;
 
@@ -13,39 +13,78 @@ impl Index {
             return Ok(Response::empty_404());
         };
         if repo.should_refresh(FetchType::Db) {
-            repo.try_refresh(None, FetchType::Db);
+            repo.try_refresh(FetchType::Db);
         }
         let repo_state = repo.state.read().unwrap();
         let mut pkgs = repo_state.packages.values().map(|v| {
-            (v.desc.name.as_ref(), v.desc.filename.as_ref(), v.desc.version.as_ref(), v.mirrors.len(), match v.cache.get() {
-                DataSource::Empty => "-",
-                DataSource::Memory(_) => "Memory",
-            })
+            let overlay = v.mirrors.iter().find(|m| repo.is_overlay(m)).map(|m| m.repo_name.as_ref());
+            let held = repo_state.filtered.get(&v.desc.name);
+            (v.desc.name.as_ref(), v.desc.filename.as_ref(), v.desc.version.as_ref(), (v.mirrors.len(), overlay), v.cache.get().label(), held)
         }).collect_vec();
         pkgs.sort_by(|a,b| {
             a.0.cmp(b.0).then_with(|| {
                 vercmp::alpm_pkg_ver_cmp(b.2, a.2)
             })
         });
+        let excluded = repo_state.filtered.iter()
+            .filter(|(name, _)| !repo_state.packages.contains_key(*name))
+            .sorted_by(|a, b| a.0.cmp(b.0))
+            .collect_vec();
     
         Ok(Response::html(template(req.raw_url(), html! {
+            p { a href="changes" { "Changes" } " (" a href="feed.atom" { "feed" } ")" }
+            @if let Some(degraded) = &repo_state.degraded {
+                p.warning { "The last refresh was rejected (" (degraded) "), so the packages from before it are still served." }
+            }
             table {
                 tr {
                     th { "Name" }
                     th { "Version" }
                     th { "Mirrors" }
                     th { "Cache State" }
+                    th { "Rule" }
                 }
-                @for (name, filename, version, mirror_count, cache_state) in pkgs {
+                @for (name, filename, version, mirrors, cache_state, held) in pkgs {
                     tr {
                         td {
-                            a href=(filename) { (name) }
+                            a href={ "package/" (name) } { (name) }
+                            " (" a href=(filename) { "file" } ")"
                             " (" a href={ (filename) ".sha256" } { "hash" } ")"
                             " (" a href={ (filename) ".sig" } { "sig" } ")"
                         }
                         td { (version) }
-                        td { (mirror_count) " / " (self.config.mirrors.len()) }
+                        td {
+                            @match mirrors {
+                                (_, Some(overlay)) => { "overlay: " (overlay) },
+                                (count, None) => { (count) " / " (repo.mirrors.len()) },
+                            }
+                        }
                         td { (cache_state) }
+                        td {
+                            @if let Some(held) = held {
+                                (self.config.rules[held.rule]) " (upstream: " (held.version) ")"
+                            }
+                        }
+                    }
+                }
+            }
+            @if !excluded.is_empty() {
+                h2 { "Filtered by rules" }
+                table {
+                    tr {
+                        th { "Name" }
+                        th { "Version" }
+                        th { "Rule" }
+                    }
+                    @for (name, filtered) in excluded {
+                        tr {
+                            td { (name) }
+                            td { (filtered.version) }
+                            td {
+                                (self.config.rules[filtered.rule])
+                                @if filtered.verdict == Verdict::Hold { " (the held version is no longer available)" }
+                            }
+                        }
                     }
                 }
             }
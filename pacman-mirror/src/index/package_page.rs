@@ -0,0 +1,152 @@
+use std::sync::Arc;
+
+use maud::{html, Markup};
+use rouille::{Request, Response};
+
+use crate::{database::{constraint::split_dep, repo::State}, date::DateTime, Index};
+
+use super::template;
+
+
+/// Fields holding package names, linked to their own pages.
+const DEP_FIELDS: &[&str] = &["DEPENDS", "MAKEDEPENDS", "CHECKDEPENDS", "OPTDEPENDS", "PROVIDES", "CONFLICTS", "REPLACES"];
+
+fn format_size(bytes: u64) -> String {
+    let mut size = bytes as f64;
+    for unit in ["B", "KiB", "MiB", "GiB"] {
+        if size < 1024.0 || unit == "GiB" {
+            return match unit {
+                "B" => format!("{bytes} B"),
+                _ => format!("{size:.1} {unit}"),
+            };
+        }
+        size /= 1024.0;
+    }
+    unreachable!()
+}
+
+fn field_value(state: &State, key: &str, value: &str) -> Markup {
+    if let ("CSIZE" | "ISIZE", Ok(bytes)) = (key, value.parse()) {
+        return html! { (format_size(bytes)) };
+    }
+    if let ("BUILDDATE", Ok(secs)) = (key, value.parse()) {
+        return html! { (DateTime::from_secs(secs)) };
+    }
+    let (name, _) = split_dep(value);
+    html! {
+        @if key == "URL" && (value.starts_with("https://") || value.starts_with("http://")) {
+            a href=(value) { (value) }
+        } @else if let Some(rest) = value.strip_prefix(name).filter(|_| DEP_FIELDS.contains(&key) && state.packages.contains_key(name)) {
+            a href=(name) { (name) } (rest)
+        } @else {
+            (value)
+        }
+    }
+}
+
+impl Index {
+    pub fn get_package_page(&self, req: &Request, repo: String, name: String) -> Response {
+        let Some(repo) = self.db.repos.get(repo.as_str()) else {
+            return Response::empty_404();
+        };
+        // built from the state of every repo, so it's taken before locking this one
+        let graph = self.db.graph();
+        // what each mirror lists, read before locking the state like a refresh does
+        let versions = repo.mirrors.iter().chain(repo.overlays.iter())
+            .map(|mirror| mirror.state.read().unwrap().packages.read()
+                .find(|v| v.name.as_ref() == name)
+                .map(|v| v.version.clone()))
+            .collect::<Vec<_>>();
+        let state = repo.state.read().unwrap();
+        let Some(pkg) = state.packages.get(name.as_str()) else {
+            return Response::empty_404();
+        };
+        let desc = &pkg.desc;
+        let required_by = graph.find(&repo.name, &desc.name).into_iter()
+            .flat_map(|id| graph.reverse[id].iter())
+            .map(|&(from, idx)| (&graph.nodes[from], graph.forward[from][idx].kind))
+            .collect::<Vec<_>>();
+        let mirrors = repo.mirrors.iter().chain(repo.overlays.iter())
+            .zip(versions)
+            .map(|(mirror, version)| (mirror, version, pkg.mirrors.iter().any(|v| Arc::ptr_eq(v, mirror))))
+            .collect::<Vec<_>>();
+
+        Response::html(template(req.raw_url(), html! {
+            h2 { (desc.name) " " (desc.version) }
+            @if let Some(text) = desc.get("DESC") {
+                p { (text) }
+            }
+            p {
+                a href={ "../" (desc.filename) } { "download" }
+                " (" a href={ "../" (desc.filename) ".sha256" } { "hash" } ")"
+                @if desc.pgpsig.is_some() {
+                    " (" a href={ "../" (desc.filename) ".sig" } { "sig" } ")"
+                }
+                " cache: " (pkg.cache.get().label())
+            }
+            @if let Some(held) = state.filtered.get(&desc.name) {
+                p.warning { "Held by " (self.config.rules[held.rule]) ", upstream has " (held.version) }
+            }
+            table {
+                @for (key, value) in desc.fields() {
+                    tr {
+                        th { (key) }
+                        td {
+                            @for (i, line) in value.lines().enumerate() {
+                                @if i > 0 { br; }
+                                (field_value(&state, key, line))
+                            }
+                        }
+                    }
+                }
+            }
+            h2 { "Required by" }
+            @if required_by.is_empty() {
+                p { "Nothing served depends on this package." }
+            } @else {
+                table {
+                    tr {
+                        th { "Package" }
+                        th { "Kind" }
+                    }
+                    @for (node, kind) in required_by {
+                        tr {
+                            td { a href={ "/" (node.repo) "/package/" (node.desc.name) } { (node.repo) "/" (node.desc.name) } }
+                            td { (kind.as_str()) }
+                        }
+                    }
+                }
+            }
+            @let graph = format!("/api/v1/repos/{}/packages/{}/graph?format=svg", repo.name, desc.name);
+            p {
+                "Dependency graph: " a href=(graph) { "depends" } ", " a href={ (graph) "&reverse=true" } { "required by" }
+            }
+            h2 { "Mirrors" }
+            table {
+                tr {
+                    th { "Mirror" }
+                    th { "Version" }
+                }
+                @for (mirror, version, serves) in mirrors {
+                    tr {
+                        td {
+                            (mirror.repo_url)
+                            @if repo.is_overlay(mirror) { " (overlay: " (mirror.repo_name) ")" }
+                        }
+                        td {
+                            @match version {
+                                Some(version) if serves => { (version) },
+                                Some(version) => { (version) " (not served)" },
+                                None => { "-" },
+                            }
+                        }
+                    }
+                }
+            }
+            @if let Some(files) = &desc.files {
+                h2 { "Files" }
+                pre { @for line in files.lines().filter(|v| !v.is_empty() && *v != "%FILES%") { (line) "\n" } }
+            }
+        }))
+    }
+}
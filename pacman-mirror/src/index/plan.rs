@@ -0,0 +1,142 @@
+use std::{cmp::Ordering, collections::HashMap, io::Read, sync::Arc};
+
+use rouille::{Request, Response};
+use serde::Serialize;
+
+use crate::{cache::DataSource, database::{package::Package, prefetch::{Job, Reason}}, Index};
+
+use super::auth;
+
+
+/// Generous for `pacman -Q` output, which is a few dozen bytes a package.
+const MAX_BODY: u64 = 4 << 20;
+
+#[derive(Serialize)]
+struct Upgrade<'a> {
+    repo: &'a str,
+    name: &'a str,
+    installed: &'a str,
+    version: &'a str,
+    filename: &'a str,
+    csize: usize,
+    cached: bool,
+}
+
+#[derive(Serialize)]
+struct Plan<'a> {
+    upgrades: Vec<Upgrade<'a>>,
+    /// Total `CSIZE` of the upgrades.
+    download_size: usize,
+    /// The part of `download_size` already in the cache.
+    cached_size: usize,
+    /// Installed packages no repo serves, like `pacman -Qm` would list.
+    foreign: Vec<&'a str>,
+    warming: bool,
+}
+
+/// What `installed` would upgrade to from `repos`, searched in order like in
+/// pacman.conf, and the installed packages none of them serves.
+fn upgrades<'a>(repos: &[(&'a str, &'a HashMap<Arc<str>, Package>)], installed: &[(&'a str, &'a str)]) -> (Vec<(&'a str, &'a str, &'a Package)>, Vec<&'a str>) {
+    let mut upgrades = Vec::new();
+    let mut foreign = Vec::new();
+    for &(name, version) in installed {
+        let found = repos.iter().find_map(|(repo, packages)| packages.get(name).map(|pkg| (*repo, pkg)));
+        match found {
+            Some((repo, pkg)) if vercmp::alpm_pkg_ver_cmp(&pkg.desc.version, version) == Ordering::Greater => upgrades.push((repo, version, pkg)),
+            Some(_) => (),
+            None => foreign.push(name),
+        }
+    }
+    (upgrades, foreign)
+}
+
+impl Index {
+    /// Takes `pacman -Q` output and lists what `pacman -Syu` would download from
+    /// this mirror. With `?warm=true` the packages not cached yet are fetched too.
+    /// Warming needs one of the admin tokens, as it makes the mirror download.
+    pub fn post_plan(&self, req: &Request) -> Response {
+        let warm = match req.get_param("warm").as_deref() {
+            None | Some("false") => false,
+            Some("true") => true,
+            Some(src) => return Response::text(format!("Invalid value for warm: {src:?}\n")).with_status_code(400),
+        };
+        if warm && let Err(res) = auth::check_bearer(req, &self.config.admin_tokens) {
+            return res;
+        }
+        let Some(body) = req.data() else {
+            return Response::empty_400();
+        };
+        // one byte past the limit tells a body that's too large from one that fits exactly
+        let mut src = Vec::new();
+        if let Err(err) = body.take(MAX_BODY + 1).read_to_end(&mut src) {
+            return Response::text(format!("Failed to read the body: {err}\n")).with_status_code(400);
+        }
+        if src.len() as u64 > MAX_BODY {
+            return Response::text(format!("The body is larger than {MAX_BODY} bytes\n")).with_status_code(413);
+        }
+        let Ok(src) = String::from_utf8(src) else {
+            return Response::text("The body is not UTF-8 text\n").with_status_code(400);
+        };
+
+        let mut installed = Vec::new();
+        for (idx, line) in src.lines().enumerate().filter(|(_, v)| !v.trim().is_empty()) {
+            let mut parts = line.split_whitespace();
+            let (Some(name), Some(version), None) = (parts.next(), parts.next(), parts.next()) else {
+                return Response::text(format!("Line {}: expected \"name version\" as printed by pacman -Q\n", idx + 1)).with_status_code(400);
+            };
+            installed.push((name, version));
+        }
+
+        let states = self.config.repo_names()
+            .filter_map(|name| self.db.repos.get(name))
+            .map(|repo| (repo.name.as_ref(), repo.state.read().unwrap()))
+            .collect::<Vec<_>>();
+        let repos = states.iter().map(|(repo, state)| (*repo, &state.packages)).collect::<Vec<_>>();
+        let (upgrades, foreign) = upgrades(&repos, &installed);
+
+        let plan = upgrades.iter().map(|&(repo, installed, pkg)| Upgrade {
+            repo,
+            name: &pkg.desc.name,
+            installed,
+            version: &pkg.desc.version,
+            filename: &pkg.desc.filename,
+            csize: pkg.desc.csize,
+            cached: !matches!(pkg.cache.get(), DataSource::Empty),
+        }).collect::<Vec<_>>();
+        if warm {
+            self.db.prefetch.push(upgrades.iter()
+                .zip(plan.iter())
+                .filter(|(_, v)| !v.cached)
+                .map(|(&(repo, _, pkg), _)| Job { repo: repo.into(), package: pkg.clone(), reason: Reason::Plan }));
+        }
+        Response::json(&Plan {
+            download_size: plan.iter().map(|v| v.csize).sum(),
+            cached_size: plan.iter().filter(|v| v.cached).map(|v| v.csize).sum(),
+            upgrades: plan,
+            foreign,
+            warming: warm,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::desc::Desc;
+
+    use super::*;
+
+    #[test]
+    fn upgrades() {
+        let packages = |descs: Vec<Arc<Desc>>| descs.into_iter()
+            .map(|desc| (desc.name.clone(), Package::new(desc)))
+            .collect::<HashMap<_, _>>();
+        let core = packages(vec![Desc::test("linux", "6.10-1", &[]), Desc::test("bash", "5.2-1", &[]), Desc::test("glibc", "2.40-1", &[])]);
+        let testing = packages(vec![Desc::test("linux", "6.11-1", &[]), Desc::test("vim", "1:9.1-1", &[])]);
+        let installed = [("linux", "6.9-1"), ("bash", "5.2-1"), ("glibc", "2.41-1"), ("vim", "9.2-1"), ("yay", "12-1")];
+        let (upgrades, foreign) = super::upgrades(&[("core", &core), ("testing", &testing)], &installed);
+        // core is searched first, and an epoch beats any version without one
+        let upgrades = upgrades.iter().map(|(repo, installed, pkg)| (*repo, *installed, pkg.desc.version.as_ref())).collect::<Vec<_>>();
+        assert_eq!(upgrades, [("core", "6.9-1", "6.10-1"), ("testing", "9.2-1", "1:9.1-1")]);
+        assert_eq!(foreign, ["yay"]);
+    }
+}
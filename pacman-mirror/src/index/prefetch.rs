@@ -0,0 +1,171 @@
+use std::sync::Arc;
+
+use log::warn;
+use maud::html;
+use rouille::{Request, Response};
+use serde::Serialize;
+
+use crate::{cache::DataSource, database::prefetch::{Job, Outcome, Reason}, Index};
+
+use super::{package::Requester, template};
+
+
+#[derive(Serialize)]
+struct JobInfo<'a> {
+    repo: &'a str,
+    filename: &'a str,
+    reason: Reason,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    outcome: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    time: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Status<'a> {
+    done: usize,
+    failed: usize,
+    bytes: u64,
+    active: Vec<JobInfo<'a>>,
+    queued: Vec<JobInfo<'a>>,
+    finished: Vec<JobInfo<'a>>,
+}
+
+fn outcome_name(outcome: &Outcome) -> &'static str {
+    match outcome {
+        Outcome::Done => "done",
+        Outcome::Cached => "cached",
+        Outcome::Removed => "removed",
+        Outcome::Failed(_) => "failed",
+    }
+}
+
+impl Index {
+    /// Starts the workers downloading whatever gets queued for prefetching.
+    pub fn start_prefetch(self: &Arc<Self>) {
+        for _ in 0..self.config.prefetch.concurrency {
+            let index = self.clone();
+            std::thread::spawn(move || loop {
+                let job = index.db.prefetch.next();
+                let outcome = index.prefetch(&job);
+                if let Outcome::Failed(err) = &outcome {
+                    warn!("Failed to prefetch {}: {err}", job.package.desc.filename);
+                }
+                index.db.prefetch.finish(&job, outcome);
+            });
+        }
+    }
+    fn prefetch(&self, job: &Job) -> Outcome {
+        // the repo may have been refreshed since the job was queued
+        let package = self.db.repos.get(&job.repo).and_then(|repo| {
+            let state = repo.state.read().unwrap();
+            let name = state.packages_by_filename.get(&job.package.desc.filename)?;
+            state.packages.get(name).cloned()
+        });
+        let Some(package) = package else {
+            return Outcome::Removed;
+        };
+        if !matches!(package.cache.get(), DataSource::Empty) {
+            return Outcome::Cached;
+        }
+        let res = match self.serve_package(package.clone(), package.desc.filename.clone(), Requester::Prefetch(self.db.prefetch.clone())) {
+            Ok(res) if res.is_success() => res,
+            Ok(_) => return Outcome::Failed("no mirror could provide it".into()),
+            Err(err) => return Outcome::Failed(format!("{err:?}").into()),
+        };
+        let (mut reader, _) = res.data.into_reader_and_size();
+        if let Err(err) = std::io::copy(&mut reader, &mut std::io::sink()) {
+            return Outcome::Failed(err.to_string().into());
+        }
+        // a failed download leaves the cache empty again
+        match package.cache.get() {
+            DataSource::Empty => Outcome::Failed("download failed".into()),
+            _ => Outcome::Done,
+        }
+    }
+    pub fn api_prefetch(&self) -> Response {
+        let progress = self.db.prefetch.progress();
+        let pending = |reason, repo, filename| JobInfo { repo, filename, reason, size: None, outcome: None, error: None, time: None };
+        Response::json(&Status {
+            done: progress.done,
+            failed: progress.failed,
+            bytes: progress.bytes,
+            active: progress.active.iter().map(|(repo, filename, reason)| pending(*reason, repo, filename)).collect(),
+            queued: progress.queued.iter().map(|job| JobInfo {
+                size: Some(job.package.desc.csize),
+                ..pending(job.reason, &job.repo, &job.package.desc.filename)
+            }).collect(),
+            finished: progress.finished.iter().map(|v| JobInfo {
+                repo: &v.repo,
+                filename: &v.filename,
+                reason: v.reason,
+                size: Some(v.size),
+                outcome: Some(outcome_name(&v.outcome)),
+                error: match &v.outcome {
+                    Outcome::Failed(err) => Some(err),
+                    _ => None,
+                },
+                time: Some(v.time.to_string()),
+            }).collect(),
+        })
+    }
+    pub fn get_prefetch(&self, req: &Request) -> Response {
+        let config = &self.config.prefetch;
+        let progress = self.db.prefetch.progress();
+        let queued_size = progress.queued.iter().map(|v| v.package.desc.csize).sum::<usize>();
+        Response::html(template(req.raw_url(), html! {
+            p {
+                "Updates of cached packages are " (if config.updates { "prefetched" } else { "not prefetched" }) ". "
+                @if !config.watch.is_empty() {
+                    "Watching " (config.watch.join(", ")) ". "
+                }
+                (config.concurrency) " at once"
+                @if config.max_rate > 0 {
+                    ", up to " (config.max_rate) " bytes/s"
+                }
+                "."
+            }
+            p {
+                (progress.done) " fetched (" (progress.bytes) " bytes), "
+                (progress.failed) " failed, "
+                (progress.queued.len()) " queued (" (queued_size) " bytes)"
+            }
+            @if !progress.active.is_empty() {
+                h2 { "Active" }
+                ul {
+                    @for (repo, filename, reason) in progress.active.iter() {
+                        li { (repo) "/" (filename) " (" (reason.as_str()) ")" }
+                    }
+                }
+            }
+            h2 { "Recent" }
+            table {
+                tr {
+                    th { "Time" }
+                    th { "File" }
+                    th { "Reason" }
+                    th { "Size" }
+                    th { "Result" }
+                }
+                @for finished in progress.finished.iter() {
+                    tr {
+                        td { (finished.time) }
+                        td { a href={ "/" (finished.repo) "/" (finished.filename) } { (finished.repo) "/" (finished.filename) } }
+                        td { (finished.reason.as_str()) }
+                        td { (finished.size) }
+                        td {
+                            (outcome_name(&finished.outcome))
+                            @if let Outcome::Failed(err) = &finished.outcome {
+                                ": " (err)
+                            }
+                        }
+                    }
+                }
+            }
+        }))
+    }
+}
@@ -3,7 +3,7 @@ use std::sync::Arc;
 use base64::{prelude::BASE64_STANDARD, Engine};
 use rouille::Response;
 
-use crate::database::Repo;
+use crate::database::{desc::Desc, Repo};
 
 
 pub enum PropertyType {
@@ -17,13 +17,20 @@ pub fn get_property(repo: Arc<Repo>, file: &str, ty: PropertyType) -> anyhow::Re
     let Some(package) = package_name.and_then(|v| repo_state.packages.get(v.as_ref())) else {
         return Ok(Response::empty_404());
     };
+    desc_property(&package.desc, ty)
+}
+
+pub fn desc_property(desc: &Desc, ty: PropertyType) -> anyhow::Result<Response> {
     Ok(match ty {
         PropertyType::PgpSig => {
-            let data = BASE64_STANDARD.decode(package.desc.pgpsig.as_ref())?;
+            let Some(pgpsig) = &desc.pgpsig else {
+                return Ok(Response::empty_404());
+            };
+            let data = BASE64_STANDARD.decode(pgpsig.as_ref())?;
             Response::from_data("application/pgp-signature", data)
         }
         PropertyType::Sha256 => {
-            Response::text(format!("{}  {}", hex::encode(&package.desc.sha256sum), package.desc.filename.as_ref()))
+            Response::text(format!("{}  {}", hex::encode(desc.sha256sum), desc.filename.as_ref()))
         }
     })
 }
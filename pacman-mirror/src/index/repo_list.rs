@@ -9,11 +9,20 @@ use super::template;
 impl Index {
     pub fn get_repo_list(&self, req: &Request) -> Response {
         Response::html(template(req.raw_url(), html! {
+            form action="/search" {
+                input type="search" name="q" placeholder="Search packages";
+            }
             ul {
-                @for repo in self.config.repos.iter() {
-                    li { a href=(repo) { (repo) } }
+                @for repo in self.config.repo_names() {
+                    li {
+                        a href=(repo) { (repo) }
+                        @if let Some(degraded) = self.db.repos.get(repo).and_then(|v| v.state.read().unwrap().degraded.clone()) {
+                            " (degraded: " (degraded) ")"
+                        }
+                    }
                 }
             }
+            p { a href="/prefetch" { "Prefetch status" } }
         }))
     }
 }
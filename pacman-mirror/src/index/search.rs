@@ -0,0 +1,114 @@
+use std::sync::Arc;
+
+use maud::html;
+use rouille::{Request, Response};
+use serde::Serialize;
+
+use crate::{database::search::{Field, Hit, Mode, Query}, Index};
+
+use super::template;
+
+
+const DEFAULT_LIMIT: usize = 50;
+const MAX_LIMIT: usize = 1000;
+
+#[derive(Serialize)]
+struct SearchResult<'a> {
+    repo: &'a str,
+    name: &'a str,
+    version: &'a str,
+    description: Option<&'a str>,
+    field: Field,
+    exact: bool,
+}
+
+struct Search {
+    text: String,
+    mode: Mode,
+    limit: usize,
+    hits: Vec<(Arc<str>, Hit)>,
+}
+
+impl Index {
+    /// `?q=` searched with `?mode=` (exact, prefix or regex), in `?repo=` if it's given.
+    fn search(&self, req: &Request) -> Result<Option<Search>, Response> {
+        let bad_request = |err: String| Response::text(format!("{err}\n")).with_status_code(400);
+        let mode = match req.get_param("mode").filter(|v| !v.is_empty()) {
+            Some(src) => src.parse().map_err(|err| bad_request(format!("{err}")))?,
+            None => Mode::Prefix,
+        };
+        let limit = match req.get_param("limit") {
+            Some(src) => src.parse::<usize>().map_err(|_| bad_request(format!("Invalid value for limit: {src:?}")))?.min(MAX_LIMIT),
+            None => DEFAULT_LIMIT,
+        };
+        let Some(text) = req.get_param("q").filter(|v| !v.trim().is_empty()) else {
+            return Ok(None);
+        };
+        let repo = req.get_param("repo").filter(|v| !v.is_empty());
+        let query = Query::new(mode, &text).map_err(|err| bad_request(format!("{err}")))?;
+        let hits = self.db.search(&query, repo.as_deref());
+        Ok(Some(Search { text, mode, limit, hits }))
+    }
+    pub fn api_search(&self, req: &Request) -> Response {
+        let search = match self.search(req) {
+            Ok(Some(search)) => search,
+            Ok(None) => return Response::text("Missing q\n").with_status_code(400),
+            Err(res) => return res,
+        };
+        Response::json(&search.hits.iter().take(search.limit).map(|(repo, hit)| SearchResult {
+            repo,
+            name: &hit.desc.name,
+            version: &hit.desc.version,
+            description: hit.desc.get("DESC"),
+            field: hit.field,
+            exact: hit.exact,
+        }).collect::<Vec<_>>())
+    }
+    pub fn get_search(&self, req: &Request) -> Response {
+        let search = match self.search(req) {
+            Ok(search) => search,
+            Err(res) => return res,
+        };
+        let text = search.as_ref().map_or("", |v| v.text.as_str());
+        let mode = search.as_ref().map_or(Mode::Prefix, |v| v.mode);
+        Response::html(template(req.raw_url(), html! {
+            form action="/search" {
+                input type="search" name="q" value=(text) autofocus;
+                " "
+                select name="mode" {
+                    @for (value, label) in [(Mode::Prefix, "prefix"), (Mode::Exact, "exact"), (Mode::Regex, "regex")] {
+                        option value=(label) selected[value == mode] { (label) }
+                    }
+                }
+                " "
+                select name="repo" {
+                    option value="" { "all repos" }
+                    @for repo in self.config.repo_names() {
+                        option value=(repo) selected[req.get_param("repo").as_deref() == Some(repo.as_ref())] { (repo) }
+                    }
+                }
+                " "
+                input type="submit" value="Search";
+            }
+            @if let Some(search) = &search {
+                p { (search.hits.len()) " results" }
+                table {
+                    tr {
+                        th { "Repo" }
+                        th { "Name" }
+                        th { "Version" }
+                        th { "Description" }
+                    }
+                    @for (repo, hit) in search.hits.iter().take(search.limit) {
+                        tr {
+                            td { a href={ "/" (repo) "/" } { (repo) } }
+                            td { a href={ "/" (repo) "/package/" (hit.desc.name) } { (hit.desc.name) } }
+                            td { (hit.desc.version) }
+                            td { (hit.desc.get("DESC").unwrap_or("")) }
+                        }
+                    }
+                }
+            }
+        }))
+    }
+}
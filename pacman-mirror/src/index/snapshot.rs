@@ -0,0 +1,98 @@
+use std::sync::Arc;
+
+use maud::html;
+use rouille::{Request, Response};
+
+use crate::{database::{repo::state::FetchType, snapshot::Snapshot}, date::DateTime, Index};
+
+use super::{database::database_response, package::Requester, property::{desc_property, PropertyType}, template};
+
+
+impl Index {
+    fn find_snapshot(&self, date: &str, repo: &str) -> anyhow::Result<Result<Arc<Snapshot>, Response>> {
+        let Some(date) = DateTime::parse_date(date) else {
+            return Ok(Err(Response::text("Expected a date such as 2024-05-01\n").with_status_code(400)));
+        };
+        let Some(repo) = self.db.repos.get(repo) else {
+            return Ok(Err(Response::empty_404()));
+        };
+        Ok(self.db.snapshots.find(repo, date)?.ok_or_else(Response::empty_404))
+    }
+    pub fn get_snapshot_list(&self, req: &Request) -> Response {
+        Response::html(template(req.raw_url(), html! {
+            @for repo in self.config.repo_names() {
+                h2 { (repo) }
+                ul {
+                    @for id in self.db.snapshots.list(repo).iter().rev() {
+                        @if let Some(created) = DateTime::parse_id(id) {
+                            li { a href={ (created.date()) "/" (repo) "/" } { (created) } }
+                        }
+                    }
+                }
+            }
+        }))
+    }
+    pub fn get_snapshot_package_list(&self, req: &Request, date: String, repo: String) -> anyhow::Result<Response> {
+        Ok(match self.find_snapshot(&date, &repo)? {
+            Ok(snapshot) => snapshot_package_list(req, &snapshot),
+            Err(res) => res,
+        })
+    }
+    pub fn get_snapshot_item(&self, date: String, repo: String, file: Arc<str>) -> anyhow::Result<Response> {
+        match self.find_snapshot(&date, &repo)? {
+            Ok(snapshot) => self.serve_snapshot_item(snapshot, &repo, file),
+            Err(res) => Ok(res),
+        }
+    }
+    pub fn serve_snapshot_item(&self, snapshot: Arc<Snapshot>, repo: &str, file: Arc<str>) -> anyhow::Result<Response> {
+        let ty = match file.strip_prefix(repo) {
+            Some(".db") => Some(FetchType::Db),
+            Some(".files") => Some(FetchType::Files),
+            _ => None,
+        };
+        if let Some(ty) = ty {
+            if ty > snapshot.ty {
+                return Ok(Response::empty_404());
+            }
+            return database_response(ty, move |writer| {
+                snapshot.sorted().into_iter().try_for_each(|package| writer.append(&package.desc))
+            });
+        }
+        let property = [(".sig", PropertyType::PgpSig), (".sha256", PropertyType::Sha256)].into_iter()
+            .find_map(|(ext, ty)| Some((file.strip_suffix(ext)?, ty)));
+        if let Some((file, ty)) = property {
+            return match snapshot.packages.get(file) {
+                Some(package) => desc_property(&package.desc, ty),
+                None => Ok(Response::empty_404()),
+            };
+        }
+        match (snapshot.packages.get(&file), self.db.repos.get(repo)) {
+            (Some(package), Some(repo)) => self.serve_package(package.clone(), file, Requester::Client(repo.clone())),
+            _ => Ok(Response::empty_404()),
+        }
+    }
+}
+
+pub fn snapshot_package_list(req: &Request, snapshot: &Snapshot) -> Response {
+    Response::html(template(req.raw_url(), html! {
+        p { "Snapshot taken " (snapshot.created) }
+        table {
+            tr {
+                th { "Name" }
+                th { "Version" }
+                th { "Cache State" }
+            }
+            @for package in snapshot.sorted() {
+                tr {
+                    td {
+                        a href=(package.desc.filename) { (package.desc.name) }
+                        " (" a href={ (package.desc.filename) ".sha256" } { "hash" } ")"
+                        " (" a href={ (package.desc.filename) ".sig" } { "sig" } ")"
+                    }
+                    td { (package.desc.version) }
+                    td { (package.cache.get().label()) }
+                }
+            }
+        }
+    }))
+}
@@ -0,0 +1,70 @@
+use std::sync::Arc;
+
+use base64::{prelude::BASE64_STANDARD, Engine};
+use log::info;
+use rouille::{Request, Response};
+
+use crate::{database::{local::{AddError, LocalRepo}, Repo}, Index};
+
+use super::auth;
+
+
+impl Index {
+    fn get_local_repo(&self, req: &Request, repo_name: &str) -> Result<(&LocalRepo, Arc<Repo>), Response> {
+        let local = self.config.local.iter().find(|v| v.name.as_ref() == repo_name);
+        let (Some(local), Some(repo)) = (local, self.db.repos.get(repo_name)) else {
+            return Err(Response::empty_404());
+        };
+        auth::check_bearer(req, &local.upload_tokens)?;
+        Ok((local, repo.clone()))
+    }
+
+    fn refresh_overlays(&self, local: &LocalRepo) {
+        // overlaid repos may be waiting on a refresh of their own, so don't hold up the response for them
+        for overlay in local.overlays.iter().filter_map(|v| self.db.repos.get(v)) {
+            let overlay = overlay.clone();
+            std::thread::spawn(move || overlay.refresh_overlays());
+        }
+    }
+
+    /// Adds the package in the request body to a local repo. A detached
+    /// signature can be sent base64 encoded in the `X-Package-Signature` header.
+    pub fn upload_package(&self, req: &Request, repo_name: String) -> anyhow::Result<Response> {
+        let (local_repo, repo) = match self.get_local_repo(req, &repo_name) {
+            Ok(v) => v,
+            Err(res) => return Ok(res),
+        };
+        let sig = match req.header("X-Package-Signature").map(|v| BASE64_STANDARD.decode(v.trim())) {
+            Some(Ok(sig)) => Some(sig),
+            Some(Err(err)) => return Ok(Response::text(format!("Invalid signature: {err}\n")).with_status_code(400)),
+            None => None,
+        };
+        let Some(body) = req.data() else {
+            return Ok(Response::empty_400());
+        };
+        let desc = match repo.upload(body, sig.as_deref()) {
+            Ok(Some(desc)) => desc,
+            Ok(None) => return Ok(Response::empty_404()),
+            Err(AddError::Invalid(err)) => return Ok(Response::text(format!("{err}\n")).with_status_code(400)),
+            Err(AddError::Io(err)) => return Err(err.into()),
+        };
+        info!("Uploaded {} to {repo_name}", desc.filename);
+        self.refresh_overlays(local_repo);
+
+        Ok(Response::text(format!("{}\n", desc.filename)).with_status_code(201))
+    }
+
+    pub fn delete_package(&self, req: &Request, repo_name: String, name: String) -> anyhow::Result<Response> {
+        let (local_repo, repo) = match self.get_local_repo(req, &repo_name) {
+            Ok(v) => v,
+            Err(res) => return Ok(res),
+        };
+        if repo.delete(&name)? == 0 {
+            return Ok(Response::empty_404());
+        }
+        info!("Removed {name} from {repo_name}");
+        self.refresh_overlays(local_repo);
+
+        Ok(Response::empty_204())
+    }
+}
@@ -3,7 +3,7 @@ use std::sync::Arc;
 use env_logger::Env;
 use log::{debug, info};
 use rouille::router;
-pub use config::Config;
+pub use config::{Args, Config};
 pub use database::Database;
 pub use index::Index;
 
@@ -11,15 +11,31 @@ mod index;
 mod cache;
 mod config;
 mod database;
+mod date;
+mod glob;
+mod http;
+mod metrics;
 
 fn main() -> anyhow::Result<()> {
     env_logger::builder()
         .parse_env(Env::new().filter_or("RUST_LOG", "info"))
         .try_init()?;
     
-    let config = Arc::new(Config::load("config.toml")?);
+    let args = Args::parse(std::env::vars(), std::env::args().skip(1))?;
+    if args.print_help {
+        println!("{}", config::args::USAGE);
+        return Ok(());
+    }
+    let config = Config::load(&args.config, &args.overrides)?;
+    if args.print_config {
+        print!("{config}");
+        return Ok(());
+    }
+    config.validate()?;
+    let config = Arc::new(config);
     let database = Arc::new(Database::new(config.clone()));
     let index = Arc::new(Index::new(database.clone()));
+    index.start_prefetch();
 
     debug!("Loaded config: {config:#?}");
     info!("Listening on {}", config.listen.as_ref());
@@ -28,9 +44,61 @@ fn main() -> anyhow::Result<()> {
         debug!("{}: {}", req.method(), req.raw_url());
         router!(req,
             (GET) (/) => { index.get_repo_list(req) },
+            (GET) (/snapshot/) => { index.get_snapshot_list(req) },
+            (GET) (/snapshot/{date: String}/{repo: String}/) => { index.get_snapshot_package_list(req, date, repo).unwrap() },
+            (GET) (/snapshot/{date: String}/{repo: String}/{file: String}) => { index.get_snapshot_item(date, repo, file.into()).unwrap() },
+            (GET) (/admin/channels) => { index.admin_channels(req) },
+            (POST) (/admin/channels/{name: String}/promote) => { index.promote_channel(req, name).unwrap() },
+            (POST) (/admin/channels/{name: String}/rollback) => { index.rollback_channel(req, name).unwrap() },
+            (GET) (/healthz) => { index.get_healthz() },
+            (GET) (/readyz) => { index.get_readyz() },
+            (GET) (/metrics) => { index.get_metrics() },
+            (GET) (/admin/downloads) => { index.admin_downloads(req) },
+            (POST) (/admin/repos/{repo: String}/refresh) => { index.admin_refresh(req, repo) },
+            (POST) (/admin/repos/{repo: String}/purge) => { index.admin_purge(req, repo) },
+            (POST) (/admin/repos/{repo: String}/verify) => { index.admin_verify(req, repo) },
+            (POST) (/admin/mirrors/quarantine) => { index.admin_quarantine(req, true) },
+            (POST) (/admin/mirrors/unquarantine) => { index.admin_quarantine(req, false) },
+            (POST) (/admin/prefetch) => { index.admin_prefetch(req) },
+            (GET) (/api/v1/repos) => { index.api_repos() },
+            (GET) (/api/v1/repos/{repo: String}/packages) => { index.api_packages(req, repo) },
+            (GET) (/api/v1/repos/{repo: String}/packages/{name: String}) => { index.api_package(repo, name) },
+            (GET) (/api/v1/repos/{repo: String}/packages/{name: String}/depends) => { index.api_dependencies(req, repo, name, false) },
+            (GET) (/api/v1/repos/{repo: String}/packages/{name: String}/rdepends) => { index.api_dependencies(req, repo, name, true) },
+            (GET) (/api/v1/repos/{repo: String}/packages/{name: String}/graph) => { index.get_dependency_graph(req, repo, name) },
+            (POST) (/api/v1/plan) => { index.post_plan(req) },
+            (GET) (/api/v1/mirrors) => { index.api_mirrors() },
+            (GET) (/api/v1/search) => { index.api_search(req) },
+            (GET) (/search) => { index.get_search(req) },
+            (GET) (/api/v1/files) => { index.api_files(req) },
+            (GET) (/files) => { index.get_files(req) },
+            (GET) (/api/v1/prefetch) => { index.api_prefetch() },
+            (GET) (/prefetch) => { index.get_prefetch(req) },
             (GET) (/{repo: String}) => { rouille::Response::redirect_301(format!("/{repo}/")) },
-            (GET) (/{repo: String}/) => { index.get_package_list(req, repo).unwrap() },
-            (GET) (/{repo: String}/{file: String}) => { index.get_item(repo.into(), file.into()).unwrap() },
+            (GET) (/{repo: String}/) => {
+                match index.is_channel(&repo) {
+                    true => index.get_channel(req, repo),
+                    false => index.get_package_list(req, repo).unwrap(),
+                }
+            },
+            (GET) (/{repo: String}/package/{name: String}) => {
+                match index.is_channel(&repo) {
+                    true => index.get_channel_item(repo, "package".into(), name.into()).unwrap(),
+                    false => index.get_package_page(req, repo, name),
+                }
+            },
+            (GET) (/{repo: String}/changes) => { index.get_changelog(req, repo) },
+            (GET) (/{repo: String}/{file: String}) => {
+                match file.as_str() {
+                    "feed.atom" => index.get_feed(req, repo),
+                    _ => index.get_item(repo.into(), file.into()).unwrap(),
+                }
+            },
+            (GET) (/{channel: String}/{repo: String}/) => { index.get_channel_package_list(req, channel, repo).unwrap() },
+            (GET) (/{channel: String}/{repo: String}/{file: String}) => { index.get_channel_item(channel, repo, file.into()).unwrap() },
+            (PUT) (/{repo: String}/) => { index.upload_package(req, repo).unwrap() },
+            (POST) (/{repo: String}/) => { index.upload_package(req, repo).unwrap() },
+            (DELETE) (/{repo: String}/{name: String}) => { index.delete_package(req, repo, name).unwrap() },
             _ => rouille::Response::empty_404()
         )
     });
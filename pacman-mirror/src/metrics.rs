@@ -0,0 +1,91 @@
+use std::{fmt::Write, sync::atomic::{AtomicU64, Ordering}, time::Duration};
+
+
+/// Upper bounds of the histogram buckets, in seconds.
+const BUCKETS: [f64; 12] = [0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0];
+
+#[derive(Default)]
+pub struct Histogram {
+    buckets: [AtomicU64; BUCKETS.len()],
+    sum_micros: AtomicU64,
+    count: AtomicU64,
+}
+
+/// Counted by `serve_package` for clients, and timed by refreshes.
+#[derive(Default)]
+pub struct RepoMetrics {
+    pub hits: AtomicU64,
+    pub misses: AtomicU64,
+    /// Bytes of packages served from the cache.
+    pub cached_bytes: AtomicU64,
+    /// Bytes of packages served while they were downloaded from a mirror.
+    pub upstream_bytes: AtomicU64,
+    pub refresh_duration: Histogram,
+}
+
+/// Requests to one mirror for one repo.
+#[derive(Default)]
+pub struct MirrorMetrics {
+    pub requests: AtomicU64,
+    pub errors: AtomicU64,
+    /// Bytes of packages downloaded, whoever asked for them.
+    pub bytes: AtomicU64,
+    /// Time until the response headers arrived.
+    pub latency: Histogram,
+}
+
+/// Builds a response in the Prometheus text format.
+#[derive(Default)]
+pub struct Writer(String);
+
+pub fn inc(counter: &AtomicU64, value: u64) {
+    counter.fetch_add(value, Ordering::Relaxed);
+}
+
+impl Histogram {
+    pub fn observe(&self, duration: Duration) {
+        let secs = duration.as_secs_f64();
+        if let Some(idx) = BUCKETS.iter().position(|v| secs <= *v) {
+            self.buckets[idx].fetch_add(1, Ordering::Relaxed);
+        }
+        self.sum_micros.fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+impl Writer {
+    pub fn family(&mut self, name: &str, ty: &str, help: &str) {
+        _ = writeln!(self.0, "# HELP {name} {help}");
+        _ = writeln!(self.0, "# TYPE {name} {ty}");
+    }
+    pub fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: impl Into<f64>) {
+        self.0.push_str(name);
+        if !labels.is_empty() {
+            let labels = labels.iter().map(|(k, v)| format!("{k}=\"{}\"", escape(v))).collect::<Vec<_>>();
+            _ = write!(self.0, "{{{}}}", labels.join(","));
+        }
+        _ = writeln!(self.0, " {}", value.into());
+    }
+    pub fn counter(&mut self, name: &str, labels: &[(&str, &str)], counter: &AtomicU64) {
+        self.sample(name, labels, counter.load(Ordering::Relaxed) as f64);
+    }
+    pub fn histogram(&mut self, name: &str, labels: &[(&str, &str)], histogram: &Histogram) {
+        let mut total = 0;
+        for (bound, count) in BUCKETS.iter().zip(histogram.buckets.iter()) {
+            total += count.load(Ordering::Relaxed);
+            let bound = bound.to_string();
+            self.sample(&format!("{name}_bucket"), &[labels, &[("le", &bound)]].concat(), total as f64);
+        }
+        let count = histogram.count.load(Ordering::Relaxed);
+        self.sample(&format!("{name}_bucket"), &[labels, &[("le", "+Inf")]].concat(), count as f64);
+        self.sample(&format!("{name}_sum"), labels, histogram.sum_micros.load(Ordering::Relaxed) as f64 / 1e6);
+        self.sample(&format!("{name}_count"), labels, count as f64);
+    }
+    pub fn finish(self) -> String {
+        self.0
+    }
+}
@@ -1,4 +1,4 @@
-use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::sync::{atomic::{AtomicUsize, Ordering}, Arc, Condvar, Mutex, RwLock};
 
 pub use read::ReplayBufferReader;
 pub use write::ReplayBufferWriter;
@@ -16,6 +16,7 @@ pub struct ReplayBuffer<T> where T: Clone {
     data: RwLock<Vec<T>>,
     state: Mutex<State>,
     cvar: Condvar,
+    readers: AtomicUsize,
 }
 
 impl<T> ReplayBuffer<T> where T: Clone {
@@ -27,10 +28,26 @@ impl<T> ReplayBuffer<T> where T: Clone {
                 size: 0,
             }),
             cvar: Condvar::new(),
+            readers: AtomicUsize::new(0),
         })
     }
     pub fn read(self: &Arc<Self>) -> ReplayBufferReader<T> {
         ReplayBufferReader::new(self.clone())
     }
+    /// How many items have been written so far.
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().size
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Whether a writer may still add items.
+    pub fn is_writing(&self) -> bool {
+        self.state.lock().unwrap().is_writing
+    }
+    /// How many readers exist right now.
+    pub fn readers(&self) -> usize {
+        self.readers.load(Ordering::Relaxed)
+    }
 }
 
@@ -1,4 +1,4 @@
-use std::{io::Read, sync::Arc};
+use std::{io::Read, sync::{atomic::Ordering, Arc}};
 
 use super::ReplayBuffer;
 
@@ -10,6 +10,7 @@ pub struct ReplayBufferReader<T> where T: Clone {
 
 impl<T> ReplayBufferReader<T> where T: Clone {
     pub fn new(base: Arc<ReplayBuffer<T>>) -> Self {
+        base.readers.fetch_add(1, Ordering::Relaxed);
         Self { base, at: 0 }
     }
     fn wait_for(&self, count: usize) {
@@ -24,6 +25,12 @@ impl<T> ReplayBufferReader<T> where T: Clone {
     }
 }
 
+impl<T> Drop for ReplayBufferReader<T> where T: Clone {
+    fn drop(&mut self) {
+        self.base.readers.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
 impl<T> Iterator for ReplayBufferReader<T> where T: Clone {
     type Item = T;
 
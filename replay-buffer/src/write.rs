@@ -30,6 +30,12 @@ impl<T> ReplayBufferWriter<T> where T: Clone {
     }
 }
 
+impl<T> Default for ReplayBufferWriter<T> where T: Clone {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Write for ReplayBufferWriter<u8> {
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
         self.extend(buf.iter().copied());